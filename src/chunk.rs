@@ -0,0 +1,247 @@
+use std::rc::Rc;
+
+use crate::{
+    intern::Symbol,
+    token::{Span, TokenKind},
+};
+
+/// A single bytecode instruction. Operands follow the opcode byte in the
+/// code stream; `u16` operands are big endian.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum OpCode {
+    /// `[index: u16]` Pushes a constant.
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    /// `[slot: u16]`
+    GetLocal,
+    /// `[slot: u16]`
+    SetLocal,
+    /// `[name: u16]`
+    GetGlobal,
+    /// `[name: u16]`
+    DefineGlobal,
+    /// `[name: u16]`
+    SetGlobal,
+    /// `[index: u16]`
+    GetUpvalue,
+    /// `[index: u16]`
+    SetUpvalue,
+    /// `[name: u16]`
+    GetProperty,
+    /// `[name: u16]`
+    SetProperty,
+    /// `[name: u16]` Pops the superclass and `this`, pushes the bound method.
+    GetSuper,
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    /// Pops the condition and both branches, pushes the chosen branch.
+    Ternary,
+    Print,
+    /// `[offset: u16]` Jumps forward.
+    Jump,
+    /// `[offset: u16]` Jumps forward if the top of the stack is falsey,
+    /// leaving it in place.
+    JumpIfFalse,
+    /// `[offset: u16]` Jumps backward.
+    Loop,
+    /// `[argument count: u8]`
+    Call,
+    /// `[function: u16]` followed by `[is local: u8, index: u16]` for each
+    /// upvalue the function captures.
+    Closure,
+    CloseUpvalue,
+    Return,
+    /// `[name: u16]`
+    Class,
+    /// Copies the superclass methods into the class on top of the stack.
+    Inherit,
+    /// `[name: u16]`
+    Method,
+    /// Replaces the value on top of the stack with the string `print` would
+    /// show for it.
+    Stringify,
+}
+
+impl OpCode {
+    const ALL: [OpCode; 40] = [
+        OpCode::Constant,
+        OpCode::Nil,
+        OpCode::True,
+        OpCode::False,
+        OpCode::Pop,
+        OpCode::GetLocal,
+        OpCode::SetLocal,
+        OpCode::GetGlobal,
+        OpCode::DefineGlobal,
+        OpCode::SetGlobal,
+        OpCode::GetUpvalue,
+        OpCode::SetUpvalue,
+        OpCode::GetProperty,
+        OpCode::SetProperty,
+        OpCode::GetSuper,
+        OpCode::Equal,
+        OpCode::NotEqual,
+        OpCode::Greater,
+        OpCode::GreaterEqual,
+        OpCode::Less,
+        OpCode::LessEqual,
+        OpCode::Add,
+        OpCode::Subtract,
+        OpCode::Multiply,
+        OpCode::Divide,
+        OpCode::Not,
+        OpCode::Negate,
+        OpCode::Ternary,
+        OpCode::Print,
+        OpCode::Jump,
+        OpCode::JumpIfFalse,
+        OpCode::Loop,
+        OpCode::Call,
+        OpCode::Closure,
+        OpCode::CloseUpvalue,
+        OpCode::Return,
+        OpCode::Class,
+        OpCode::Inherit,
+        OpCode::Method,
+        OpCode::Stringify,
+    ];
+
+    /// The source operator this instruction was compiled from, used to
+    /// rebuild the token reported by runtime errors.
+    pub fn operator(self) -> TokenKind {
+        match self {
+            OpCode::Equal => TokenKind::EqualEqual,
+            OpCode::NotEqual => TokenKind::BangEqual,
+            OpCode::Greater => TokenKind::Greater,
+            OpCode::GreaterEqual => TokenKind::GreaterEqual,
+            OpCode::Less => TokenKind::Less,
+            OpCode::LessEqual => TokenKind::LessEqual,
+            OpCode::Add => TokenKind::Plus,
+            OpCode::Subtract | OpCode::Negate => TokenKind::Minus,
+            OpCode::Multiply => TokenKind::Star,
+            OpCode::Divide => TokenKind::Slash,
+            OpCode::Not => TokenKind::Bang,
+            OpCode::Ternary => TokenKind::QuestionMark,
+            _ => unreachable!("{:?} is not an operator", self),
+        }
+    }
+}
+
+impl TryFrom<u8> for OpCode {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        OpCode::ALL.get(value as usize).copied().ok_or(value)
+    }
+}
+
+/// A compile-time constant. Constants are kept apart from runtime values so
+/// that chunks do not depend on a heap.
+#[derive(Debug, Clone)]
+pub enum Constant {
+    Number(f64),
+    String(Symbol),
+    Function(Rc<FunctionProto>),
+}
+
+/// A compiled function: its code plus what the VM needs to call it.
+#[derive(Debug, Clone, Default)]
+pub struct FunctionProto {
+    pub name: String,
+    pub arity: usize,
+    pub upvalue_count: usize,
+    pub chunk: Chunk,
+}
+
+/// Source position of the instruction starting at `offset`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineInfo {
+    pub offset: usize,
+    pub line: usize,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Constant>,
+    /// One entry per instruction, ordered by offset.
+    pub lines: Vec<LineInfo>,
+}
+
+impl Chunk {
+    /// Appends an instruction and records where in the source it came from.
+    pub fn write_op(&mut self, op: OpCode, line: usize, span: Span) {
+        self.lines.push(LineInfo {
+            offset: self.code.len(),
+            line,
+            span,
+        });
+        self.code.push(op as u8);
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.code.push(value);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.code.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_be_bytes([self.code[offset], self.code[offset + 1]])
+    }
+
+    pub fn patch_u16(&mut self, offset: usize, value: u16) {
+        self.code[offset..offset + 2].copy_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn add_constant(&mut self, constant: Constant) -> usize {
+        self.constants.push(constant);
+        self.constants.len() - 1
+    }
+
+    /// The source position of the instruction covering `offset`.
+    pub fn line_info(&self, offset: usize) -> LineInfo {
+        let index = self.lines.partition_point(|info| info.offset <= offset);
+        self.lines[index.saturating_sub(1)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opcode_round_trip() {
+        for op in OpCode::ALL {
+            assert_eq!(OpCode::try_from(op as u8), Ok(op));
+        }
+        assert_eq!(OpCode::try_from(OpCode::ALL.len() as u8), Err(40));
+    }
+
+    #[test]
+    fn test_line_info() {
+        let mut chunk = Chunk::default();
+        chunk.write_op(OpCode::Constant, 1, Span::new(0, 1));
+        chunk.write_u16(0);
+        chunk.write_op(OpCode::Print, 2, Span::new(4, 9));
+
+        assert_eq!(chunk.line_info(2).line, 1);
+        assert_eq!(chunk.line_info(3).span, Span::new(4, 9));
+    }
+}
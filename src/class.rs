@@ -0,0 +1,143 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::{
+    collector::Node,
+    function::{Callable, Function},
+    intern::Symbol,
+    interpreter::{Interpreter, Output, RuntimeError},
+    token::Token,
+};
+
+pub struct Class {
+    name: String,
+    superclass: Option<Rc<Class>>,
+    methods: HashMap<Symbol, Rc<Function>>,
+}
+
+impl Class {
+    pub fn new(
+        name: String,
+        superclass: Option<Rc<Class>>,
+        methods: HashMap<Symbol, Rc<Function>>,
+    ) -> Self {
+        Class {
+            name,
+            superclass,
+            methods,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn trace(&self, tracer: &mut impl FnMut(Node)) {
+        if let Some(superclass) = &self.superclass {
+            tracer(Node::Class(Rc::clone(superclass)));
+        }
+        for method in self.methods.values() {
+            tracer(Node::Function(Rc::clone(method)));
+        }
+    }
+
+    pub fn find_method(&self, name: Symbol) -> Option<Rc<Function>> {
+        match self.methods.get(&name) {
+            Some(method) => Some(Rc::clone(method)),
+            None => self
+                .superclass
+                .as_ref()
+                .and_then(|superclass| superclass.find_method(name)),
+        }
+    }
+}
+
+/// Classes are called through their `Rc` so the new instance can keep a
+/// reference to the class it was created from.
+impl Callable for Rc<Class> {
+    fn arity(&self) -> usize {
+        self.find_method(Symbol::INIT)
+            .map_or(0, |init| init.arity())
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Output>,
+    ) -> Result<Output, RuntimeError> {
+        let instance = interpreter.new_instance(Rc::clone(self));
+
+        if let Some(initializer) = self.find_method(Symbol::INIT) {
+            initializer
+                .bind(Rc::clone(&instance))
+                .call(interpreter, arguments)?;
+        }
+
+        Ok(Output::Instance(instance))
+    }
+}
+
+impl std::fmt::Debug for Class {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Class({})", self.name)
+    }
+}
+
+pub struct Instance {
+    class: Rc<Class>,
+    fields: RefCell<HashMap<Symbol, Output>>,
+}
+
+impl Instance {
+    pub fn new(class: Rc<Class>) -> Self {
+        Instance {
+            class,
+            fields: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn class_name(&self) -> &str {
+        self.class.name()
+    }
+
+    /// Returns false without tracing while the fields are being changed.
+    pub fn trace(&self, tracer: &mut impl FnMut(Node)) -> bool {
+        let Ok(fields) = self.fields.try_borrow() else {
+            return false;
+        };
+
+        tracer(Node::Class(Rc::clone(&self.class)));
+        fields
+            .values()
+            .filter_map(Node::from_output)
+            .for_each(tracer);
+        true
+    }
+
+    pub fn take_fields(&self) -> HashMap<Symbol, Output> {
+        self.fields.take()
+    }
+
+    /// Fields shadow methods; methods are bound to `instance` on lookup.
+    pub fn get(instance: &Rc<Instance>, name: &Token) -> Result<Output, RuntimeError> {
+        let key = name.symbol();
+
+        if let Some(value) = instance.fields.borrow().get(&key) {
+            return Ok(value.clone());
+        }
+
+        match instance.class.find_method(key) {
+            Some(method) => Ok(Output::Function(Rc::new(method.bind(Rc::clone(instance))))),
+            None => Err(RuntimeError::UndefinedProperty(name.to_string(), name.span)),
+        }
+    }
+
+    pub fn set(&self, name: &Token, value: Output) {
+        self.fields.borrow_mut().insert(name.symbol(), value);
+    }
+}
+
+impl std::fmt::Debug for Instance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Instance({})", self.class.name)
+    }
+}
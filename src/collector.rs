@@ -0,0 +1,288 @@
+use std::{
+    cell::RefCell,
+    collections::{hash_map::Entry, HashMap},
+    rc::{Rc, Weak},
+    time::Instant,
+};
+
+use crate::{
+    class::{Class, Instance},
+    environment::Environment,
+    function::Function,
+    interpreter::Output,
+    object::{GcStats, GROWTH_FACTOR, INITIAL_THRESHOLD},
+};
+
+/// A reference-counted object of the tree-walker that can be part of a
+/// cycle.
+#[derive(Clone)]
+pub enum Node {
+    Environment(Rc<RefCell<Environment>>),
+    Instance(Rc<Instance>),
+    Function(Rc<Function>),
+    Class(Rc<Class>),
+}
+
+impl Node {
+    pub fn from_output(value: &Output) -> Option<Node> {
+        match value {
+            Output::Function(v) => Some(Node::Function(Rc::clone(v))),
+            Output::Class(v) => Some(Node::Class(Rc::clone(v))),
+            Output::Instance(v) => Some(Node::Instance(Rc::clone(v))),
+            Output::Number(_)
+            | Output::Boolean(_)
+            | Output::String(_)
+            | Output::Nil
+            | Output::NativeFunction(_) => None,
+        }
+    }
+
+    fn key(&self) -> usize {
+        match self {
+            Node::Environment(v) => Rc::as_ptr(v) as *const () as usize,
+            Node::Instance(v) => Rc::as_ptr(v) as *const () as usize,
+            Node::Function(v) => Rc::as_ptr(v) as *const () as usize,
+            Node::Class(v) => Rc::as_ptr(v) as *const () as usize,
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Node::Environment(v) => Rc::strong_count(v),
+            Node::Instance(v) => Rc::strong_count(v),
+            Node::Function(v) => Rc::strong_count(v),
+            Node::Class(v) => Rc::strong_count(v),
+        }
+    }
+
+    /// Calls `tracer` with every node this one holds a strong reference to.
+    /// Returns false without tracing when the node is borrowed elsewhere.
+    fn trace(&self, tracer: &mut impl FnMut(Node)) -> bool {
+        match self {
+            Node::Environment(v) => match v.try_borrow() {
+                Ok(environment) => {
+                    environment.trace(tracer);
+                    true
+                }
+                Err(_) => false,
+            },
+            Node::Instance(v) => v.trace(tracer),
+            Node::Function(v) => {
+                v.trace(tracer);
+                true
+            }
+            Node::Class(v) => {
+                v.trace(tracer);
+                true
+            }
+        }
+    }
+}
+
+/// An object the collector starts its search for cycles from. Every cycle
+/// the tree-walker can build goes through an environment or the fields of
+/// an instance, so those are the only ones tracked.
+enum Tracked {
+    Environment(Weak<RefCell<Environment>>),
+    Instance(Weak<Instance>),
+}
+
+impl Tracked {
+    fn upgrade(&self) -> Option<Node> {
+        match self {
+            Tracked::Environment(v) => v.upgrade().map(Node::Environment),
+            Tracked::Instance(v) => v.upgrade().map(Node::Instance),
+        }
+    }
+
+    fn is_live(&self) -> bool {
+        match self {
+            Tracked::Environment(v) => v.strong_count() > 0,
+            Tracked::Instance(v) => v.strong_count() > 0,
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Tracked::Environment(_) => std::mem::size_of::<RefCell<Environment>>(),
+            Tracked::Instance(_) => std::mem::size_of::<Instance>(),
+        }
+    }
+}
+
+struct Candidate {
+    node: Node,
+    /// Strong references held by other nodes found in this collection.
+    internal: usize,
+    traced: bool,
+    marked: bool,
+}
+
+/// Frees reference cycles among the tree-walker's objects.
+///
+/// The tree-walker shares objects through `Rc`, which frees everything
+/// except cycles. The collector finds those by trial deletion: it walks
+/// every object reachable from the tracked ones and counts the references
+/// they hold to each other. An object with more strong references than
+/// that is also held from outside the graph, by a global, a value the
+/// interpreter is working on or anything else, and is live along with
+/// everything it reaches. The rest can only be reached from each other, so
+/// their environments and fields are emptied, which drops the cycle.
+///
+/// No roots are needed, so a collection is safe at any allocation.
+pub struct Collector {
+    tracked: Vec<Tracked>,
+    next_gc: usize,
+    /// Collect at every allocation, to flush out miscounted references.
+    stress: bool,
+    stats: GcStats,
+}
+
+impl Default for Collector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Collector {
+    pub fn new() -> Self {
+        Collector {
+            tracked: Vec::new(),
+            next_gc: INITIAL_THRESHOLD,
+            stress: false,
+            stats: GcStats::default(),
+        }
+    }
+
+    pub fn set_stress(&mut self, stress: bool) {
+        self.stress = stress;
+    }
+
+    /// Rc frees most objects on its own, so live bytes are counted afresh.
+    pub fn stats(&self) -> GcStats {
+        GcStats {
+            bytes_live: self.live_bytes(),
+            ..self.stats
+        }
+    }
+
+    fn live_bytes(&self) -> usize {
+        self.tracked
+            .iter()
+            .filter(|tracked| tracked.is_live())
+            .map(Tracked::size)
+            .sum()
+    }
+
+    pub fn track_environment(&mut self, environment: &Rc<RefCell<Environment>>) {
+        self.track(Tracked::Environment(Rc::downgrade(environment)));
+    }
+
+    pub fn track_instance(&mut self, instance: &Rc<Instance>) {
+        self.track(Tracked::Instance(Rc::downgrade(instance)));
+    }
+
+    fn track(&mut self, tracked: Tracked) {
+        let size = tracked.size();
+        self.stats.bytes_allocated += size;
+        self.stats.bytes_live += size;
+        self.tracked.push(tracked);
+
+        if self.stress || self.stats.bytes_live > self.next_gc {
+            self.collect();
+        }
+    }
+
+    /// Frees every cycle that nothing outside it refers to.
+    pub fn collect(&mut self) {
+        let start = Instant::now();
+
+        self.tracked.retain(Tracked::is_live);
+        let mut candidates: HashMap<usize, Candidate> = HashMap::new();
+        let mut pending = Vec::new();
+        for node in self.tracked.iter().filter_map(Tracked::upgrade) {
+            if let Entry::Vacant(entry) = candidates.entry(node.key()) {
+                pending.push(node.key());
+                entry.insert(Candidate {
+                    node,
+                    internal: 0,
+                    traced: false,
+                    marked: false,
+                });
+            }
+        }
+
+        // Find everything reachable and count the references between them.
+        while let Some(key) = pending.pop() {
+            let mut children = Vec::new();
+            let candidate = candidates.get_mut(&key).expect("pending candidate");
+            candidate.traced = candidate.node.trace(&mut |child| children.push(child));
+
+            for child in children {
+                match candidates.entry(child.key()) {
+                    Entry::Occupied(mut entry) => entry.get_mut().internal += 1,
+                    Entry::Vacant(entry) => {
+                        pending.push(child.key());
+                        entry.insert(Candidate {
+                            node: child,
+                            internal: 1,
+                            traced: false,
+                            marked: false,
+                        });
+                    }
+                }
+            }
+        }
+
+        // `candidates` holds one reference to each node itself.
+        let mut gray: Vec<usize> = candidates
+            .iter()
+            .filter(|(_, c)| !c.traced || c.node.strong_count() - 1 > c.internal)
+            .map(|(&key, _)| key)
+            .collect();
+        for key in &gray {
+            candidates.get_mut(key).expect("root candidate").marked = true;
+        }
+
+        while let Some(key) = gray.pop() {
+            let mut children = Vec::new();
+            candidates[&key]
+                .node
+                .trace(&mut |child| children.push(child));
+
+            for child in children {
+                let candidate = candidates.get_mut(&child.key()).expect("traced child");
+                if !candidate.marked {
+                    candidate.marked = true;
+                    gray.push(child.key());
+                }
+            }
+        }
+
+        // Empty the garbage first and drop it all at once afterwards, so no
+        // node is freed while the collector still looks at it.
+        let mut environments = Vec::new();
+        let mut fields = Vec::new();
+        for candidate in candidates.values().filter(|c| !c.marked) {
+            match &candidate.node {
+                Node::Environment(v) => environments.push(std::mem::take(&mut *v.borrow_mut())),
+                Node::Instance(v) => fields.push(v.take_fields()),
+                Node::Function(_) | Node::Class(_) => {}
+            }
+            self.stats.objects_freed += 1;
+        }
+        drop(environments);
+        drop(fields);
+        drop(candidates);
+
+        self.tracked.retain(Tracked::is_live);
+        let live = self.live_bytes();
+        self.stats.bytes_live = live;
+        self.next_gc = (live * GROWTH_FACTOR).max(INITIAL_THRESHOLD);
+
+        let pause = start.elapsed();
+        self.stats.collections += 1;
+        self.stats.total_pause += pause;
+        self.stats.max_pause = self.stats.max_pause.max(pause);
+    }
+}
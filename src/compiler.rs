@@ -0,0 +1,687 @@
+use crate::{
+    chunk::{Chunk, Constant, FunctionProto, OpCode},
+    expr::*,
+    intern::Symbol,
+    stmt::{self, Stmt},
+    token::{Span, Token, TokenKind},
+};
+
+#[derive(Debug)]
+pub enum CompileError {
+    TooManyConstants(Span),
+    TooManyLocals(Span),
+    TooManyUpvalues(Span),
+    JumpTooLarge(Span),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FunctionKind {
+    Script,
+    Function,
+    Method,
+    Initializer,
+}
+
+#[derive(Debug)]
+struct Local {
+    name: Symbol,
+    /// `None` until the variable's initializer has been compiled.
+    depth: Option<usize>,
+    captured: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct UpvalueRef {
+    is_local: bool,
+    index: u16,
+}
+
+/// Per-function compilation state. Functions nest, so the compiler keeps a
+/// stack of these.
+#[derive(Debug)]
+struct FunctionState {
+    function: FunctionProto,
+    kind: FunctionKind,
+    locals: Vec<Local>,
+    upvalues: Vec<UpvalueRef>,
+    scope_depth: usize,
+}
+
+impl FunctionState {
+    fn new(name: String, kind: FunctionKind) -> Self {
+        // Slot 0 holds the callee, or the receiver for methods.
+        let receiver = match kind {
+            FunctionKind::Method | FunctionKind::Initializer => Symbol::THIS,
+            FunctionKind::Script | FunctionKind::Function => Symbol::EMPTY,
+        };
+
+        FunctionState {
+            function: FunctionProto {
+                name,
+                ..FunctionProto::default()
+            },
+            kind,
+            locals: vec![Local {
+                name: receiver,
+                depth: Some(0),
+                captured: false,
+            }],
+            upvalues: Vec::new(),
+            scope_depth: 0,
+        }
+    }
+}
+
+/// Compiles a resolved program into bytecode for the `Vm`. Locals become
+/// stack slots and captured variables become upvalues; globals stay late
+/// bound by name as in the tree-walker.
+#[derive(Debug)]
+pub struct Compiler {
+    states: Vec<FunctionState>,
+    line_starts: Vec<usize>,
+    span: Span,
+    errors: Vec<CompileError>,
+}
+
+impl Compiler {
+    pub fn new(source: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        Compiler {
+            states: Vec::new(),
+            line_starts,
+            span: Span::new(0, 0),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn compile(mut self, statements: &[Stmt]) -> Result<FunctionProto, Vec<CompileError>> {
+        self.states.push(FunctionState::new(
+            "script".to_string(),
+            FunctionKind::Script,
+        ));
+
+        for statement in statements {
+            self.statement(statement);
+        }
+        self.emit_return();
+
+        let state = self.states.pop().expect("script state");
+        if self.errors.is_empty() {
+            Ok(state.function)
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn statement(&mut self, statement: &Stmt) {
+        match statement {
+            Stmt::Expression(v) => {
+                self.expression(&v.expr);
+                self.emit(OpCode::Pop);
+            }
+            Stmt::Print(v) => {
+                self.expression(&v.expr);
+                self.emit(OpCode::Print);
+            }
+            Stmt::Var(v) => self.var_declaration(v),
+            Stmt::Block(v) => {
+                self.begin_scope();
+                for statement in &v.statements {
+                    self.statement(statement);
+                }
+                self.end_scope();
+            }
+            Stmt::If(v) => self.if_statement(v),
+            Stmt::While(v) => self.while_statement(v),
+            Stmt::Function(v) => {
+                let global = self.declare_variable(&v.name);
+                self.mark_initialized();
+                self.function(v, FunctionKind::Function);
+                self.define_variable(global);
+            }
+            Stmt::Return(v) => self.return_statement(v),
+            Stmt::Class(v) => self.class_declaration(v),
+        }
+    }
+
+    fn var_declaration(&mut self, statement: &stmt::Var) {
+        let global = self.declare_variable(&statement.name);
+
+        match &statement.initializer {
+            Some(initializer) => self.expression(initializer),
+            None => self.emit(OpCode::Nil),
+        }
+
+        self.define_variable(global);
+    }
+
+    fn if_statement(&mut self, statement: &stmt::If) {
+        self.expression(&statement.condition);
+
+        let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit(OpCode::Pop);
+        self.statement(&statement.then_branch);
+
+        let else_jump = self.emit_jump(OpCode::Jump);
+        self.patch_jump(then_jump);
+        self.emit(OpCode::Pop);
+
+        if let Some(else_branch) = &statement.else_branch {
+            self.statement(else_branch);
+        }
+        self.patch_jump(else_jump);
+    }
+
+    fn while_statement(&mut self, statement: &stmt::While) {
+        let loop_start = self.chunk().code.len();
+        self.expression(&statement.condition);
+
+        let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit(OpCode::Pop);
+        self.statement(&statement.body);
+        self.emit_loop(loop_start);
+
+        self.patch_jump(exit_jump);
+        self.emit(OpCode::Pop);
+    }
+
+    fn return_statement(&mut self, statement: &stmt::Return) {
+        self.span = statement.keyword.span;
+
+        match &statement.value {
+            Some(value) => {
+                self.expression(value);
+                self.emit(OpCode::Return);
+            }
+            None => self.emit_return(),
+        }
+    }
+
+    fn class_declaration(&mut self, statement: &stmt::Class) {
+        let name = statement.name;
+        let name_constant = self.identifier_constant(&name);
+        let global = self.declare_variable(&name);
+
+        self.emit_with_u16(OpCode::Class, name_constant);
+        self.define_variable(global);
+
+        if let Some(superclass) = &statement.superclass {
+            self.named_variable(&superclass.name, false);
+
+            self.begin_scope();
+            self.add_local(Symbol::SUPER);
+            self.mark_initialized();
+
+            self.named_variable(&name, false);
+            self.span = superclass.name.span;
+            self.emit(OpCode::Inherit);
+        }
+
+        self.named_variable(&name, false);
+        for method in &statement.methods {
+            let method_name = self.identifier_constant(&method.name);
+            let kind = if method.name.symbol() == Symbol::INIT {
+                FunctionKind::Initializer
+            } else {
+                FunctionKind::Method
+            };
+
+            self.function(method, kind);
+            self.emit_with_u16(OpCode::Method, method_name);
+        }
+        self.emit(OpCode::Pop);
+
+        if statement.superclass.is_some() {
+            self.end_scope();
+        }
+    }
+
+    /// Stringifies each part and concatenates them left to right. Text
+    /// pieces are already strings.
+    fn interpolation(&mut self, expr: &Interpolation) {
+        for (i, part) in expr.parts.iter().enumerate() {
+            self.expression(part);
+            self.span = expr.span;
+            if !matches!(
+                part,
+                Expr::Literal(Literal {
+                    value: LiteralValue::String(_),
+                    ..
+                })
+            ) {
+                self.emit(OpCode::Stringify);
+            }
+            if i > 0 {
+                self.emit(OpCode::Add);
+            }
+        }
+    }
+
+    fn function(&mut self, function: &stmt::Function, kind: FunctionKind) {
+        self.span = function.name.span;
+        self.states
+            .push(FunctionState::new(function.name.to_string(), kind));
+        self.begin_scope();
+
+        for param in &function.params {
+            self.add_local(param.symbol());
+            self.mark_initialized();
+        }
+        self.current().function.arity = function.params.len();
+
+        for statement in &function.body {
+            self.statement(statement);
+        }
+        self.emit_return();
+
+        let mut state = self.states.pop().expect("function state");
+        state.function.upvalue_count = state.upvalues.len();
+
+        self.span = function.name.span;
+        let constant = self.make_constant(Constant::Function(state.function.into()));
+        self.emit_with_u16(OpCode::Closure, constant);
+        for upvalue in state.upvalues {
+            self.chunk().write_u8(upvalue.is_local as u8);
+            self.chunk().write_u16(upvalue.index);
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal(v) => match v.value {
+                LiteralValue::Number(n) => self.emit_constant(Constant::Number(n)),
+                LiteralValue::String(s) => self.emit_constant(Constant::String(s)),
+                LiteralValue::Boolean(true) => self.emit(OpCode::True),
+                LiteralValue::Boolean(false) => self.emit(OpCode::False),
+                LiteralValue::Nil => self.emit(OpCode::Nil),
+            },
+            Expr::Unary(v) => {
+                self.expression(&v.right);
+                self.span = v.operator.span;
+                match v.operator.kind {
+                    TokenKind::Minus => self.emit(OpCode::Negate),
+                    TokenKind::Bang => self.emit(OpCode::Not),
+                    _ => unreachable!("Invalid token"),
+                }
+            }
+            Expr::Binary(v) => self.binary(v),
+            Expr::Grouping(v) => self.expression(&v.expr),
+            Expr::Ternary(v) => {
+                // The tree-walker evaluates both branches before checking
+                // the condition; keep the same observable behavior.
+                self.expression(&v.left);
+                self.expression(&v.middle);
+                self.expression(&v.right);
+                self.span = v.left_operator.span;
+                self.emit(OpCode::Ternary);
+            }
+            Expr::Variable(v) => self.named_variable(&v.name, false),
+            Expr::Assign(v) => {
+                self.expression(&v.value);
+                self.named_variable(&v.name, true);
+            }
+            Expr::Logical(v) => self.logical(v),
+            Expr::Call(v) => {
+                self.expression(&v.callee);
+                for argument in &v.arguments {
+                    self.expression(argument);
+                }
+                self.span = v.paren.span;
+                self.emit(OpCode::Call);
+                self.chunk().write_u8(v.arguments.len() as u8);
+            }
+            Expr::Get(v) => {
+                self.expression(&v.object);
+                let name = self.identifier_constant(&v.name);
+                self.span = v.name.span;
+                self.emit_with_u16(OpCode::GetProperty, name);
+            }
+            Expr::Set(v) => {
+                self.expression(&v.object);
+                self.expression(&v.value);
+                let name = self.identifier_constant(&v.name);
+                self.span = v.name.span;
+                self.emit_with_u16(OpCode::SetProperty, name);
+            }
+            Expr::This(v) => self.named_variable(&v.keyword, false),
+            Expr::Interpolation(v) => self.interpolation(v),
+            Expr::Super(v) => {
+                let this = Token {
+                    kind: TokenKind::This,
+                    span: v.keyword.span,
+                };
+                self.named_variable(&this, false);
+                self.named_variable(&v.keyword, false);
+                let method = self.identifier_constant(&v.method);
+                self.span = v.method.span;
+                self.emit_with_u16(OpCode::GetSuper, method);
+            }
+        }
+    }
+
+    fn binary(&mut self, expr: &Binary) {
+        self.expression(&expr.left);
+
+        if expr.operator.kind == TokenKind::Comma {
+            self.emit(OpCode::Pop);
+            self.expression(&expr.right);
+            return;
+        }
+
+        self.expression(&expr.right);
+        self.span = expr.operator.span;
+
+        let op = match expr.operator.kind {
+            TokenKind::Minus => OpCode::Subtract,
+            TokenKind::Plus => OpCode::Add,
+            TokenKind::Slash => OpCode::Divide,
+            TokenKind::Star => OpCode::Multiply,
+            TokenKind::Greater => OpCode::Greater,
+            TokenKind::GreaterEqual => OpCode::GreaterEqual,
+            TokenKind::Less => OpCode::Less,
+            TokenKind::LessEqual => OpCode::LessEqual,
+            TokenKind::BangEqual => OpCode::NotEqual,
+            TokenKind::EqualEqual => OpCode::Equal,
+            _ => unreachable!("Unreachable code"),
+        };
+        self.emit(op);
+    }
+
+    fn logical(&mut self, expr: &Logical) {
+        self.expression(&expr.left);
+        self.span = expr.operator.span;
+
+        match expr.operator.kind {
+            TokenKind::And => {
+                let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit(OpCode::Pop);
+                self.expression(&expr.right);
+                self.patch_jump(end_jump);
+            }
+            TokenKind::Or => {
+                let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                let end_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(else_jump);
+                self.emit(OpCode::Pop);
+                self.expression(&expr.right);
+                self.patch_jump(end_jump);
+            }
+            _ => unreachable!("Invalid token"),
+        }
+    }
+
+    fn named_variable(&mut self, name: &Token, assign: bool) {
+        self.span = name.span;
+        let key = name.symbol();
+        let level = self.states.len() - 1;
+
+        let (op, index) = if let Some(slot) = self.resolve_local(level, key) {
+            let op = if assign {
+                OpCode::SetLocal
+            } else {
+                OpCode::GetLocal
+            };
+            (op, slot)
+        } else if let Some(index) = self.resolve_upvalue(level, key) {
+            let op = if assign {
+                OpCode::SetUpvalue
+            } else {
+                OpCode::GetUpvalue
+            };
+            (op, index)
+        } else {
+            let op = if assign {
+                OpCode::SetGlobal
+            } else {
+                OpCode::GetGlobal
+            };
+            (op, self.identifier_constant(name))
+        };
+
+        self.emit_with_u16(op, index);
+    }
+
+    fn resolve_local(&mut self, level: usize, name: Symbol) -> Option<u16> {
+        self.states[level]
+            .locals
+            .iter()
+            .rposition(|local| local.name == name)
+            .map(|slot| slot as u16)
+    }
+
+    fn resolve_upvalue(&mut self, level: usize, name: Symbol) -> Option<u16> {
+        if level == 0 {
+            return None;
+        }
+
+        if let Some(slot) = self.resolve_local(level - 1, name) {
+            self.states[level - 1].locals[slot as usize].captured = true;
+            return Some(self.add_upvalue(level, slot, true));
+        }
+
+        let index = self.resolve_upvalue(level - 1, name)?;
+        Some(self.add_upvalue(level, index, false))
+    }
+
+    fn add_upvalue(&mut self, level: usize, index: u16, is_local: bool) -> u16 {
+        let upvalue = UpvalueRef { is_local, index };
+        let upvalues = &mut self.states[level].upvalues;
+
+        if let Some(existing) = upvalues.iter().position(|v| *v == upvalue) {
+            return existing as u16;
+        }
+
+        if upvalues.len() > u16::MAX as usize {
+            self.errors.push(CompileError::TooManyUpvalues(self.span));
+            return 0;
+        }
+
+        upvalues.push(upvalue);
+        (upvalues.len() - 1) as u16
+    }
+
+    /// Declares `name` in the current scope. Returns the name constant when
+    /// the variable is a global.
+    fn declare_variable(&mut self, name: &Token) -> Option<u16> {
+        self.span = name.span;
+
+        if self.current().scope_depth == 0 {
+            return Some(self.identifier_constant(name));
+        }
+
+        self.add_local(name.symbol());
+        None
+    }
+
+    fn define_variable(&mut self, global: Option<u16>) {
+        match global {
+            Some(name) => self.emit_with_u16(OpCode::DefineGlobal, name),
+            None => self.mark_initialized(),
+        }
+    }
+
+    fn add_local(&mut self, name: Symbol) {
+        if self.current().locals.len() > u16::MAX as usize {
+            self.errors.push(CompileError::TooManyLocals(self.span));
+            return;
+        }
+
+        self.current().locals.push(Local {
+            name,
+            depth: None,
+            captured: false,
+        });
+    }
+
+    fn mark_initialized(&mut self) {
+        let state = self.current();
+        if state.scope_depth == 0 {
+            return;
+        }
+
+        let depth = state.scope_depth;
+        if let Some(local) = state.locals.last_mut() {
+            local.depth = Some(depth);
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.current().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        let state = self.current();
+        state.scope_depth -= 1;
+        let depth = state.scope_depth;
+
+        while let Some(local) = self.current().locals.last() {
+            if local.depth.is_some_and(|d| d <= depth) {
+                break;
+            }
+
+            let op = if local.captured {
+                OpCode::CloseUpvalue
+            } else {
+                OpCode::Pop
+            };
+            self.current().locals.pop();
+            self.emit(op);
+        }
+    }
+
+    fn identifier_constant(&mut self, name: &Token) -> u16 {
+        self.make_constant(Constant::String(name.symbol()))
+    }
+
+    fn make_constant(&mut self, constant: Constant) -> u16 {
+        let index = self.chunk().add_constant(constant);
+        if index > u16::MAX as usize {
+            self.errors.push(CompileError::TooManyConstants(self.span));
+            return 0;
+        }
+        index as u16
+    }
+
+    fn emit_constant(&mut self, constant: Constant) {
+        let index = self.make_constant(constant);
+        self.emit_with_u16(OpCode::Constant, index);
+    }
+
+    fn emit_return(&mut self) {
+        if self.current().kind == FunctionKind::Initializer {
+            self.emit_with_u16(OpCode::GetLocal, 0);
+        } else {
+            self.emit(OpCode::Nil);
+        }
+        self.emit(OpCode::Return);
+    }
+
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit_with_u16(op, u16::MAX);
+        self.chunk().code.len() - 2
+    }
+
+    fn patch_jump(&mut self, offset: usize) {
+        let jump = self.chunk().code.len() - offset - 2;
+        if jump > u16::MAX as usize {
+            self.errors.push(CompileError::JumpTooLarge(self.span));
+            return;
+        }
+        self.chunk().patch_u16(offset, jump as u16);
+    }
+
+    fn emit_loop(&mut self, loop_start: usize) {
+        self.emit(OpCode::Loop);
+        let offset = self.chunk().code.len() + 2 - loop_start;
+        if offset > u16::MAX as usize {
+            self.errors.push(CompileError::JumpTooLarge(self.span));
+        }
+        self.chunk().write_u16(offset as u16);
+    }
+
+    fn emit_with_u16(&mut self, op: OpCode, operand: u16) {
+        self.emit(op);
+        self.chunk().write_u16(operand);
+    }
+
+    fn emit(&mut self, op: OpCode) {
+        let span = self.span;
+        let line = self
+            .line_starts
+            .partition_point(|&start| start <= span.start());
+        self.chunk().write_op(op, line, span);
+    }
+
+    fn current(&mut self) -> &mut FunctionState {
+        self.states.last_mut().expect("compiler state")
+    }
+
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.current().function.chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{lexer::Lexer, parser::Parser};
+
+    use super::*;
+
+    fn compile(src: &str) -> FunctionProto {
+        let (statements, errors) = Parser::new(Lexer::new(src)).parse_program();
+        assert!(errors.is_empty());
+        Compiler::new(src).compile(&statements).unwrap()
+    }
+
+    #[test]
+    fn test_compile_expression() {
+        let function = compile("print 1 + 2;");
+
+        assert_eq!(
+            function.chunk.code,
+            vec![
+                OpCode::Constant as u8,
+                0,
+                0,
+                OpCode::Constant as u8,
+                0,
+                1,
+                OpCode::Add as u8,
+                OpCode::Print as u8,
+                OpCode::Nil as u8,
+                OpCode::Return as u8,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_locals_and_upvalues() {
+        let function = compile("{ var a = 1; fun f() { return a; } }");
+
+        let Some(Constant::Function(f)) = function.chunk.constants.get(1) else {
+            panic!("expected function constant");
+        };
+        assert_eq!(f.upvalue_count, 1);
+        assert_eq!(f.chunk.code[0], OpCode::GetUpvalue as u8);
+        assert_eq!(
+            function.chunk.code[function.chunk.code.len() - 4..],
+            [
+                OpCode::Pop as u8,
+                OpCode::CloseUpvalue as u8,
+                OpCode::Nil as u8,
+                OpCode::Return as u8
+            ]
+        );
+    }
+
+    #[test]
+    fn test_line_table() {
+        let function = compile("var a = 1;\nprint a;");
+
+        let info = function.chunk.line_info(function.chunk.code.len() - 3);
+        assert_eq!(info.line, 2);
+    }
+}
@@ -0,0 +1,200 @@
+use crate::token::{SourceId, Span};
+
+/// A named source text that spans can be resolved against.
+#[derive(Debug, Clone, Copy)]
+pub struct SourceFile<'a> {
+    name: &'a str,
+    text: &'a str,
+}
+
+impl<'a> SourceFile<'a> {
+    pub fn new(name: &'a str, text: &'a str) -> Self {
+        SourceFile { name, text }
+    }
+
+    pub fn text(&self) -> &'a str {
+        self.text
+    }
+
+    /// Resolves a byte span to its 1-based line and column and the text of
+    /// the line it starts on.
+    pub fn locate(&self, span: Span) -> Location {
+        let start = span.start().min(self.text.len());
+        let line_start = self.text[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = self.text[start..]
+            .find('\n')
+            .map_or(self.text.len(), |i| start + i);
+
+        let line_text = self.text[line_start..line_end].trim_end_matches('\r');
+        let prefix = &self.text[line_start..start];
+        let underlined = &self.text[start..span.end().clamp(start, line_end)];
+
+        Location {
+            file: self.name.to_owned(),
+            line: self.text[..line_start].matches('\n').count() + 1,
+            column: prefix.chars().count() + 1,
+            line_text: line_text.to_owned(),
+            indent: prefix
+                .chars()
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect(),
+            width: underlined.chars().count().max(1),
+        }
+    }
+}
+
+/// Every source text of a session, so that spans from different files and
+/// REPL entries can be resolved after their trees have been mixed together.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: Vec<(String, String)>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        SourceMap { files: Vec::new() }
+    }
+
+    pub fn add(&mut self, name: &str, text: String) -> SourceId {
+        self.files.push((name.to_owned(), text));
+        SourceId::new(self.files.len() - 1)
+    }
+
+    pub fn get(&self, id: SourceId) -> Option<SourceFile<'_>> {
+        self.files
+            .get(id.index())
+            .map(|(name, text)| SourceFile::new(name, text))
+    }
+}
+
+/// A span resolved against its source text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    line_text: String,
+    indent: String,
+    width: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Option<Span>,
+    pub location: Option<Box<Location>>,
+}
+
+impl Diagnostic {
+    pub fn new(message: String, span: Option<Span>) -> Self {
+        Diagnostic {
+            message,
+            span,
+            location: None,
+        }
+    }
+
+    pub fn with_source(mut self, source: &SourceFile) -> Self {
+        self.location = self.span.map(|span| Box::new(source.locate(span)));
+        self
+    }
+
+    /// Like `with_source`, picking the source the span points into.
+    pub fn with_sources(self, sources: &SourceMap) -> Self {
+        match self.span.and_then(|span| sources.get(span.source())) {
+            Some(source) => self.with_source(&source),
+            None => self,
+        }
+    }
+
+    /// Renders the diagnostic under `title`, e.g.
+    ///
+    /// ```text
+    /// Runtime Error: Undefined variable 'b'
+    ///  --> script.lox:2:7
+    ///   |
+    /// 2 | print b;
+    ///   |       ^
+    /// ```
+    pub fn render(&self, title: &str) -> String {
+        let Some(location) = &self.location else {
+            return match self.span {
+                Some(span) => format!("{}: {} at {}", title, self.message, span),
+                None => format!("{}: {}", title, self.message),
+            };
+        };
+
+        let gutter = " ".repeat(location.line.to_string().len());
+        format!(
+            "{}: {}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}",
+            title,
+            self.message,
+            gutter,
+            location.file,
+            location.line,
+            location.column,
+            gutter,
+            location.line,
+            location.line_text,
+            gutter,
+            location.indent,
+            "^".repeat(location.width)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locate() {
+        let source = SourceFile::new("test.lox", "var a = 1;\n\tprint añb;\n");
+        let location = source.locate(Span::new(19, 23));
+
+        assert_eq!(location.line, 2);
+        assert_eq!(location.column, 9);
+        assert_eq!(location.width, 3);
+    }
+
+    #[test]
+    fn test_render() {
+        let source = SourceFile::new("test.lox", "var a = 1;\nprint b;");
+        let diagnostic = Diagnostic::new(
+            "Undefined variable 'b'".to_string(),
+            Some(Span::new(17, 18)),
+        )
+        .with_source(&source);
+
+        assert_eq!(
+            diagnostic.render("Runtime Error"),
+            "Runtime Error: Undefined variable 'b'\n --> test.lox:2:7\n  |\n2 | print b;\n  |       ^"
+        );
+    }
+
+    #[test]
+    fn test_source_map() {
+        let mut sources = SourceMap::new();
+        sources.add("a.lox", "print 1;".to_string());
+        let b = sources.add("b.lox", "var x;\nprint y;".to_string());
+
+        let diagnostic = Diagnostic::new(
+            "Undefined variable 'y'".to_string(),
+            Some(Span::new(13, 14).with_source(b)),
+        )
+        .with_sources(&sources);
+
+        let location = diagnostic.location.unwrap();
+        assert_eq!((location.file.as_str(), location.line), ("b.lox", 2));
+    }
+
+    #[test]
+    fn test_render_without_source() {
+        let diagnostic = Diagnostic::new("Division by zero".to_string(), None);
+
+        assert_eq!(
+            diagnostic.render("Runtime Error"),
+            "Runtime Error: Division by zero"
+        );
+    }
+}
@@ -0,0 +1,207 @@
+use std::fmt::Write;
+
+use crate::chunk::{Chunk, Constant, FunctionProto, OpCode};
+
+/// Renders `function` and every function nested in it, e.g.
+///
+/// ```text
+/// == script ==
+/// 0000    1 Constant            0 '1'
+/// 0003    | Print
+/// 0004    | Nil
+/// 0005    | Return
+/// ```
+///
+/// Each line shows the offset, the source line (`|` when unchanged), the
+/// opcode and its operands.
+pub fn disassemble(function: &FunctionProto) -> String {
+    let mut out = String::new();
+    disassemble_function(&mut out, function);
+    out
+}
+
+fn disassemble_function(out: &mut String, function: &FunctionProto) {
+    writeln!(out, "== {} ==", function.name).unwrap();
+
+    let chunk = &function.chunk;
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        offset = disassemble_instruction(out, chunk, offset);
+    }
+
+    for constant in &chunk.constants {
+        if let Constant::Function(function) = constant {
+            writeln!(out).unwrap();
+            disassemble_function(out, function);
+        }
+    }
+}
+
+/// Writes the instruction at `offset` and returns the offset of the next one.
+pub fn disassemble_instruction(out: &mut String, chunk: &Chunk, offset: usize) -> usize {
+    let line = chunk.line_info(offset).line;
+    let same_line = offset > 0 && chunk.line_info(offset - 1).line == line;
+
+    write!(out, "{:04} ", offset).unwrap();
+    if same_line {
+        write!(out, "   | ").unwrap();
+    } else {
+        write!(out, "{:4} ", line).unwrap();
+    }
+
+    let op = match OpCode::try_from(chunk.code[offset]) {
+        Ok(op) => op,
+        Err(byte) => {
+            writeln!(out, "Unknown opcode {}", byte).unwrap();
+            return offset + 1;
+        }
+    };
+    let name = format!("{:?}", op);
+
+    match op {
+        OpCode::Constant
+        | OpCode::GetGlobal
+        | OpCode::DefineGlobal
+        | OpCode::SetGlobal
+        | OpCode::GetProperty
+        | OpCode::SetProperty
+        | OpCode::GetSuper
+        | OpCode::Class
+        | OpCode::Method => {
+            let index = chunk.read_u16(offset + 1);
+            writeln!(
+                out,
+                "{:<16} {:4} {}",
+                name,
+                index,
+                format_constant(&chunk.constants[index as usize])
+            )
+            .unwrap();
+            offset + 3
+        }
+        OpCode::GetLocal | OpCode::SetLocal | OpCode::GetUpvalue | OpCode::SetUpvalue => {
+            writeln!(out, "{:<16} {:4}", name, chunk.read_u16(offset + 1)).unwrap();
+            offset + 3
+        }
+        OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop => {
+            let jump = chunk.read_u16(offset + 1) as usize;
+            let target = if op == OpCode::Loop {
+                offset + 3 - jump
+            } else {
+                offset + 3 + jump
+            };
+            writeln!(out, "{:<16} {:4} -> {:04}", name, offset, target).unwrap();
+            offset + 3
+        }
+        OpCode::Call => {
+            writeln!(out, "{:<16} {:4}", name, chunk.code[offset + 1]).unwrap();
+            offset + 2
+        }
+        OpCode::Closure => {
+            let index = chunk.read_u16(offset + 1);
+            let constant = &chunk.constants[index as usize];
+            writeln!(
+                out,
+                "{:<16} {:4} {}",
+                name,
+                index,
+                format_constant(constant)
+            )
+            .unwrap();
+
+            let upvalue_count = match constant {
+                Constant::Function(function) => function.upvalue_count,
+                _ => 0,
+            };
+
+            let mut offset = offset + 3;
+            for _ in 0..upvalue_count {
+                let kind = if chunk.code[offset] == 1 {
+                    "local"
+                } else {
+                    "upvalue"
+                };
+                writeln!(
+                    out,
+                    "{:04}    |                     {} {}",
+                    offset,
+                    kind,
+                    chunk.read_u16(offset + 1)
+                )
+                .unwrap();
+                offset += 3;
+            }
+            offset
+        }
+        _ => {
+            writeln!(out, "{}", name).unwrap();
+            offset + 1
+        }
+    }
+}
+
+fn format_constant(constant: &Constant) -> String {
+    match constant {
+        Constant::Number(v) => format!("'{}'", v),
+        Constant::String(v) => format!("{:?}", v.as_str()),
+        Constant::Function(v) => format!("<fn {}>", v.name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{compiler::Compiler, lexer::Lexer, parser::Parser};
+
+    use super::*;
+
+    fn disassemble_source(src: &str) -> String {
+        let (statements, errors) = Parser::new(Lexer::new(src)).parse_program();
+        assert!(errors.is_empty());
+        disassemble(&Compiler::new(src).compile(&statements).unwrap())
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let src = "var a = \"hi\";\nfun f(x) {\n  if (x) return a;\n  return x;\n}\nprint f(1);";
+
+        assert_eq!(
+            disassemble_source(src),
+            "\
+== script ==
+0000    1 Constant            1 \"hi\"
+0003    | DefineGlobal        0 \"a\"
+0006    2 Closure             3 <fn f>
+0009    | DefineGlobal        2 \"f\"
+0012    6 GetGlobal           4 \"f\"
+0015    | Constant            5 '1'
+0018    | Call                1
+0020    | Print
+0021    | Nil
+0022    | Return
+
+== f ==
+0000    3 GetLocal            1
+0003    | JumpIfFalse         3 -> 0014
+0006    | Pop
+0007    | GetGlobal           0 \"a\"
+0010    | Return
+0011    | Jump               11 -> 0015
+0014    | Pop
+0015    4 GetLocal            1
+0018    | Return
+0019    | Nil
+0020    | Return
+"
+        );
+    }
+
+    #[test]
+    fn test_disassemble_upvalues() {
+        let output = disassemble_source("{ var a = 1; fun f() { a = 2; } }");
+
+        assert!(output.contains(
+            "0003    | Closure             1 <fn f>\n0006    |                     local 1\n"
+        ));
+        assert!(output.contains("SetUpvalue          0\n"));
+    }
+}
@@ -0,0 +1,111 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::{
+    collector::Node,
+    intern::Symbol,
+    interpreter::{Output, RuntimeError},
+    token::Token,
+};
+
+/// A local scope. Values are stored by slot in declaration order, matching
+/// the slots handed out by the resolver, so lookups never compare names.
+#[derive(Debug, Default)]
+pub struct Environment {
+    values: Vec<Output>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    pub fn new(enclosing: Option<Rc<RefCell<Environment>>>) -> Self {
+        Environment {
+            values: Vec::new(),
+            enclosing,
+        }
+    }
+
+    pub fn define(&mut self, value: Output) -> usize {
+        self.values.push(value);
+        self.values.len() - 1
+    }
+
+    pub fn get_at(&self, depth: usize, slot: usize) -> Output {
+        if depth == 0 {
+            self.values[slot].clone()
+        } else {
+            self.ancestor(depth).borrow().values[slot].clone()
+        }
+    }
+
+    pub fn assign_at(&mut self, depth: usize, slot: usize, value: Output) {
+        if depth == 0 {
+            self.values[slot] = value;
+        } else {
+            self.ancestor(depth).borrow_mut().values[slot] = value;
+        }
+    }
+
+    pub fn trace(&self, tracer: &mut impl FnMut(Node)) {
+        self.values
+            .iter()
+            .filter_map(Node::from_output)
+            .for_each(&mut *tracer);
+        if let Some(enclosing) = &self.enclosing {
+            tracer(Node::Environment(Rc::clone(enclosing)));
+        }
+    }
+
+    fn ancestor(&self, depth: usize) -> Rc<RefCell<Environment>> {
+        let mut environment = self
+            .enclosing
+            .clone()
+            .expect("resolver produced a depth deeper than the scope chain");
+
+        for _ in 1..depth {
+            let enclosing = environment
+                .borrow()
+                .enclosing
+                .clone()
+                .expect("resolver produced a depth deeper than the scope chain");
+            environment = enclosing;
+        }
+
+        environment
+    }
+}
+
+/// Top-level variables. These are late bound by name so that functions can
+/// refer to globals declared after them; names are interned, so a lookup
+/// hashes a `Symbol` rather than the name's characters.
+#[derive(Debug, Default)]
+pub struct Globals {
+    values: HashMap<Symbol, Output>,
+}
+
+impl Globals {
+    pub fn new() -> Self {
+        Globals {
+            values: HashMap::new(),
+        }
+    }
+
+    pub fn define(&mut self, name: Symbol, value: Output) {
+        self.values.insert(name, value);
+    }
+
+    pub fn get(&self, name: &Token) -> Result<Output, RuntimeError> {
+        match self.values.get(&name.symbol()) {
+            Some(value) => Ok(value.clone()),
+            None => Err(RuntimeError::UndefinedVariable(name.to_string(), name.span)),
+        }
+    }
+
+    pub fn assign(&mut self, name: &Token, value: Output) -> Result<(), RuntimeError> {
+        match self.values.get_mut(&name.symbol()) {
+            Some(slot) => {
+                *slot = value;
+                Ok(())
+            }
+            None => Err(RuntimeError::UndefinedVariable(name.to_string(), name.span)),
+        }
+    }
+}
@@ -1,62 +1,145 @@
-use crate::token::{Token, TokenKind};
+use crate::{
+    intern::Symbol,
+    token::{Span, Token, TokenKind},
+};
 
-#[derive(Debug)]
-pub enum Expr<'a> {
-    Literal(Literal<'a>),
-    Unary(Unary<'a>),
-    Binary(Binary<'a>),
-    Grouping(Grouping<'a>),
-    Ternary(Ternary<'a>),
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Literal(Literal),
+    Unary(Unary),
+    Binary(Binary),
+    Grouping(Grouping),
+    Ternary(Ternary),
+    Variable(Variable),
+    Assign(Assign),
+    Logical(Logical),
+    Call(Call),
+    Get(Get),
+    Set(Set),
+    This(This),
+    Super(Super),
+    Interpolation(Interpolation),
 }
 
-#[derive(Debug)]
-pub struct Literal<'a> {
-    pub value: LiteralValue<'a>,
+/// Where a local variable lives at runtime: how many scopes up from the
+/// current one, and its slot within that scope. Filled in by the resolver;
+/// `None` means the name is a global.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Binding {
+    pub depth: usize,
+    pub slot: usize,
 }
 
-#[derive(Debug)]
-pub enum LiteralValue<'a> {
+#[derive(Debug, Clone)]
+pub struct Literal {
+    pub value: LiteralValue,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum LiteralValue {
     Number(f64),
-    String(&'a str),
+    String(Symbol),
     Boolean(bool),
     Nil,
 }
 
-#[derive(Debug)]
-pub struct Unary<'a> {
-    pub operator: Token<'a>,
-    pub right: Box<Expr<'a>>,
+#[derive(Debug, Clone)]
+pub struct Unary {
+    pub operator: Token,
+    pub right: Box<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Binary {
+    pub left: Box<Expr>,
+    pub operator: Token,
+    pub right: Box<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Logical {
+    pub left: Box<Expr>,
+    pub operator: Token,
+    pub right: Box<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub callee: Box<Expr>,
+    pub paren: Token,
+    pub arguments: Vec<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Get {
+    pub object: Box<Expr>,
+    pub name: Token,
+}
+
+#[derive(Debug, Clone)]
+pub struct Set {
+    pub object: Box<Expr>,
+    pub name: Token,
+    pub value: Box<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct This {
+    pub keyword: Token,
+    pub binding: Option<Binding>,
 }
 
-#[derive(Debug)]
-pub struct Binary<'a> {
-    pub left: Box<Expr<'a>>,
-    pub operator: Token<'a>,
-    pub right: Box<Expr<'a>>,
+#[derive(Debug, Clone)]
+pub struct Super {
+    pub keyword: Token,
+    pub method: Token,
+    pub binding: Option<Binding>,
 }
 
-#[derive(Debug)]
-pub struct Ternary<'a> {
-    pub left: Box<Expr<'a>>,
-    pub left_operator: Token<'a>,
-    pub middle: Box<Expr<'a>>,
-    pub right_operator: Token<'a>,
-    pub right: Box<Expr<'a>>,
+#[derive(Debug, Clone)]
+pub struct Ternary {
+    pub left: Box<Expr>,
+    pub left_operator: Token,
+    pub middle: Box<Expr>,
+    pub right_operator: Token,
+    pub right: Box<Expr>,
 }
 
-#[derive(Debug)]
-pub struct Grouping<'a> {
-    pub expr: Box<Expr<'a>>,
+#[derive(Debug, Clone)]
+pub struct Grouping {
+    pub expr: Box<Expr>,
 }
 
-impl<'a> Literal<'a> {
-    pub fn new(value: Token<'a>) -> Self {
+#[derive(Debug, Clone)]
+pub struct Variable {
+    pub name: Token,
+    pub binding: Option<Binding>,
+}
+
+/// A string with embedded expressions, as in `"a ${b} c"`. The text pieces
+/// are string literals and empty ones are left out.
+#[derive(Debug, Clone)]
+pub struct Interpolation {
+    pub parts: Vec<Expr>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Assign {
+    pub name: Token,
+    pub value: Box<Expr>,
+    pub binding: Option<Binding>,
+}
+
+impl Literal {
+    pub fn new(value: Token) -> Self {
         Literal::from(value)
     }
 }
 
-impl<'a> Unary<'a> {
-    pub fn new(operator: Token<'a>, right: Expr<'a>) -> Self {
+impl Unary {
+    pub fn new(operator: Token, right: Expr) -> Self {
         Unary {
             operator,
             right: Box::new(right),
@@ -64,8 +147,8 @@ impl<'a> Unary<'a> {
     }
 }
 
-impl<'a> Binary<'a> {
-    pub fn new(left: Expr<'a>, operator: Token<'a>, right: Expr<'a>) -> Self {
+impl Binary {
+    pub fn new(left: Expr, operator: Token, right: Expr) -> Self {
         Binary {
             left: Box::new(left),
             operator,
@@ -74,13 +157,71 @@ impl<'a> Binary<'a> {
     }
 }
 
-impl<'a> Ternary<'a> {
+impl Logical {
+    pub fn new(left: Expr, operator: Token, right: Expr) -> Self {
+        Logical {
+            left: Box::new(left),
+            operator,
+            right: Box::new(right),
+        }
+    }
+}
+
+impl Call {
+    pub fn new(callee: Expr, paren: Token, arguments: Vec<Expr>) -> Self {
+        Call {
+            callee: Box::new(callee),
+            paren,
+            arguments,
+        }
+    }
+}
+
+impl Get {
+    pub fn new(object: Expr, name: Token) -> Self {
+        Get {
+            object: Box::new(object),
+            name,
+        }
+    }
+}
+
+impl Set {
+    pub fn new(object: Expr, name: Token, value: Expr) -> Self {
+        Set {
+            object: Box::new(object),
+            name,
+            value: Box::new(value),
+        }
+    }
+}
+
+impl This {
+    pub fn new(keyword: Token) -> Self {
+        This {
+            keyword,
+            binding: None,
+        }
+    }
+}
+
+impl Super {
+    pub fn new(keyword: Token, method: Token) -> Self {
+        Super {
+            keyword,
+            method,
+            binding: None,
+        }
+    }
+}
+
+impl Ternary {
     pub fn new(
-        left: Expr<'a>,
-        left_operator: Token<'a>,
-        middle: Expr<'a>,
-        right_operator: Token<'a>,
-        right: Expr<'a>,
+        left: Expr,
+        left_operator: Token,
+        middle: Expr,
+        right_operator: Token,
+        right: Expr,
     ) -> Self {
         Ternary {
             left: Box::new(left),
@@ -92,38 +233,82 @@ impl<'a> Ternary<'a> {
     }
 }
 
-impl<'a> Grouping<'a> {
-    pub fn new(expr: Expr<'a>) -> Self {
+impl Grouping {
+    pub fn new(expr: Expr) -> Self {
         Grouping {
             expr: Box::new(expr),
         }
     }
 }
 
-impl<'a> From<Token<'a>> for Literal<'a> {
-    fn from(value: Token<'a>) -> Self {
-        match value.kind {
-            TokenKind::Number(v) => Literal {
-                value: LiteralValue::Number(v),
-            },
-            TokenKind::String(v) => Literal {
-                value: LiteralValue::String(v),
-            },
-            TokenKind::True => Literal {
-                value: LiteralValue::Boolean(true),
-            },
-            TokenKind::False => Literal {
-                value: LiteralValue::Boolean(false),
-            },
-            TokenKind::Nil => Literal {
-                value: LiteralValue::Nil,
-            },
+impl Variable {
+    pub fn new(name: Token) -> Self {
+        Variable {
+            name,
+            binding: None,
+        }
+    }
+}
+
+impl Assign {
+    pub fn new(name: Token, value: Expr) -> Self {
+        Assign {
+            name,
+            value: Box::new(value),
+            binding: None,
+        }
+    }
+}
+
+impl Interpolation {
+    pub fn new(parts: Vec<Expr>, span: Span) -> Self {
+        Interpolation { parts, span }
+    }
+}
+
+impl From<Token> for Literal {
+    fn from(value: Token) -> Self {
+        let value_span = value.span;
+        let value = match value.kind {
+            TokenKind::Number(v) => LiteralValue::Number(v),
+            TokenKind::String(v) => LiteralValue::String(v),
+            TokenKind::True => LiteralValue::Boolean(true),
+            TokenKind::False => LiteralValue::Boolean(false),
+            TokenKind::Nil => LiteralValue::Nil,
             _ => unreachable!(),
+        };
+
+        Literal {
+            value,
+            span: value_span,
         }
     }
 }
 
-impl<'a> std::fmt::Display for Expr<'a> {
+impl Expr {
+    /// The source text the expression was parsed from, minus any
+    /// surrounding parentheses.
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Literal(v) => v.span,
+            Expr::Unary(v) => v.operator.span.to(v.right.span()),
+            Expr::Binary(v) => v.left.span().to(v.right.span()),
+            Expr::Grouping(v) => v.expr.span(),
+            Expr::Ternary(v) => v.left.span().to(v.right.span()),
+            Expr::Variable(v) => v.name.span,
+            Expr::Assign(v) => v.name.span.to(v.value.span()),
+            Expr::Logical(v) => v.left.span().to(v.right.span()),
+            Expr::Call(v) => v.callee.span().to(v.paren.span),
+            Expr::Get(v) => v.object.span().to(v.name.span),
+            Expr::Set(v) => v.object.span().to(v.value.span()),
+            Expr::This(v) => v.keyword.span,
+            Expr::Super(v) => v.keyword.span.to(v.method.span),
+            Expr::Interpolation(v) => v.span,
+        }
+    }
+}
+
+impl std::fmt::Display for Expr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Expr::Binary(v) => write!(f, "{}", v),
@@ -131,11 +316,20 @@ impl<'a> std::fmt::Display for Expr<'a> {
             Expr::Grouping(v) => write!(f, "{}", v),
             Expr::Unary(v) => write!(f, "{}", v),
             Expr::Ternary(v) => write!(f, "{}", v),
+            Expr::Variable(v) => write!(f, "{}", v),
+            Expr::Assign(v) => write!(f, "{}", v),
+            Expr::Logical(v) => write!(f, "{}", v),
+            Expr::Call(v) => write!(f, "{}", v),
+            Expr::Get(v) => write!(f, "{}", v),
+            Expr::Set(v) => write!(f, "{}", v),
+            Expr::This(v) => write!(f, "{}", v),
+            Expr::Super(v) => write!(f, "{}", v),
+            Expr::Interpolation(v) => write!(f, "{}", v),
         }
     }
 }
 
-impl<'a> std::fmt::Display for Literal<'a> {
+impl std::fmt::Display for Literal {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.value {
             LiteralValue::String(v) => write!(f, "{}", v),
@@ -149,25 +343,65 @@ impl<'a> std::fmt::Display for Literal<'a> {
     }
 }
 
-impl<'a> std::fmt::Display for Unary<'a> {
+impl std::fmt::Display for Unary {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "({} {})", self.operator, self.right)
     }
 }
 
-impl<'a> std::fmt::Display for Binary<'a> {
+impl std::fmt::Display for Binary {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "({} {} {})", self.operator, self.left, self.right)
     }
 }
 
-impl<'a> std::fmt::Display for Grouping<'a> {
+impl std::fmt::Display for Logical {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({} {} {})", self.operator, self.left, self.right)
+    }
+}
+
+impl std::fmt::Display for Call {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(call {}", self.callee)?;
+        for argument in &self.arguments {
+            write!(f, " {}", argument)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl std::fmt::Display for Get {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(. {} {})", self.object, self.name)
+    }
+}
+
+impl std::fmt::Display for Set {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(= (. {} {}) {})", self.object, self.name, self.value)
+    }
+}
+
+impl std::fmt::Display for This {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.keyword)
+    }
+}
+
+impl std::fmt::Display for Super {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(. {} {})", self.keyword, self.method)
+    }
+}
+
+impl std::fmt::Display for Grouping {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "(group {})", self.expr)
     }
 }
 
-impl<'a> std::fmt::Display for Ternary<'a> {
+impl std::fmt::Display for Ternary {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
@@ -176,3 +410,25 @@ impl<'a> std::fmt::Display for Ternary<'a> {
         )
     }
 }
+
+impl std::fmt::Display for Variable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl std::fmt::Display for Assign {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(= {} {})", self.name, self.value)
+    }
+}
+
+impl std::fmt::Display for Interpolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(interpolate")?;
+        for part in &self.parts {
+            write!(f, " {}", part)?;
+        }
+        write!(f, ")")
+    }
+}
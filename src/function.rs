@@ -0,0 +1,160 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    class::Instance,
+    collector::Node,
+    environment::Environment,
+    interpreter::{Interpreter, Output, RuntimeError, Unwind},
+    stmt,
+};
+
+/// Native stack left before `Function::call` switches to a fresh segment.
+const RED_ZONE: usize = 128 * 1024;
+/// Size of each native stack segment allocated for deep recursion.
+const STACK_SEGMENT: usize = 2 * 1024 * 1024;
+
+pub trait Callable {
+    fn arity(&self) -> usize;
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Output>,
+    ) -> Result<Output, RuntimeError>;
+}
+
+pub struct Function {
+    declaration: Rc<stmt::Function>,
+    closure: Option<Rc<RefCell<Environment>>>,
+    is_initializer: bool,
+}
+
+impl Function {
+    pub fn new(
+        declaration: Rc<stmt::Function>,
+        closure: Option<Rc<RefCell<Environment>>>,
+        is_initializer: bool,
+    ) -> Self {
+        Function {
+            declaration,
+            closure,
+            is_initializer,
+        }
+    }
+
+    /// Returns a copy of this method whose closure defines `this` as `instance`.
+    pub fn bind(&self, instance: Rc<Instance>) -> Function {
+        let mut environment = Environment::new(self.closure.clone());
+        environment.define(Output::Instance(instance));
+
+        Function {
+            declaration: Rc::clone(&self.declaration),
+            closure: Some(Rc::new(RefCell::new(environment))),
+            is_initializer: self.is_initializer,
+        }
+    }
+
+    /// The instance a bound method's closure holds in slot 0.
+    fn this(&self) -> Output {
+        match &self.closure {
+            Some(closure) => closure.borrow().get_at(0, 0),
+            None => Output::Nil,
+        }
+    }
+
+    pub fn trace(&self, tracer: &mut impl FnMut(Node)) {
+        if let Some(closure) = &self.closure {
+            tracer(Node::Environment(Rc::clone(closure)));
+        }
+    }
+
+    pub fn name(&self) -> String {
+        self.declaration.name.to_string()
+    }
+}
+
+impl Callable for Function {
+    fn arity(&self) -> usize {
+        self.declaration.params.len()
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Output>,
+    ) -> Result<Output, RuntimeError> {
+        let mut environment = Environment::new(self.closure.clone());
+
+        for argument in arguments {
+            environment.define(argument);
+        }
+
+        // Each Lox call recurses through several Rust frames, so grow the
+        // native stack on demand rather than abort on deep but valid input.
+        let result = stacker::maybe_grow(RED_ZONE, STACK_SEGMENT, || {
+            interpreter.execute_block(&self.declaration.body, environment)
+        });
+
+        match result {
+            Err(Unwind::Error(e)) => Err(e),
+            _ if self.is_initializer => Ok(self.this()),
+            Ok(()) => Ok(Output::Nil),
+            Err(Unwind::Return(value)) => Ok(value),
+        }
+    }
+}
+
+impl std::fmt::Debug for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Function({})", self.declaration.name)
+    }
+}
+
+pub struct NativeFunction {
+    name: &'static str,
+    arity: usize,
+    function: fn(&[Output]) -> Output,
+}
+
+impl NativeFunction {
+    pub fn new(name: &'static str, arity: usize, function: fn(&[Output]) -> Output) -> Self {
+        NativeFunction {
+            name,
+            arity,
+            function,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl Callable for NativeFunction {
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Interpreter,
+        arguments: Vec<Output>,
+    ) -> Result<Output, RuntimeError> {
+        Ok((self.function)(&arguments))
+    }
+}
+
+impl std::fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "NativeFunction({})", self.name)
+    }
+}
+
+/// Seconds since the Unix epoch, used for benchmarking scripts.
+pub fn clock(_arguments: &[Output]) -> Output {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+
+    Output::Number(now.as_secs_f64())
+}
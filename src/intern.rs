@@ -0,0 +1,89 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+/// Handle to an interned string. Identifier names and string literals are
+/// interned by the lexer, so comparing or hashing them never looks at their
+/// characters.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    pub const INIT: Symbol = Symbol(0);
+    pub const THIS: Symbol = Symbol(1);
+    pub const SUPER: Symbol = Symbol(2);
+    pub const EMPTY: Symbol = Symbol(3);
+
+    const PREDEFINED: [&'static str; 4] = ["init", "this", "super", ""];
+
+    pub fn intern(value: &str) -> Symbol {
+        INTERNER.with(|interner| interner.borrow_mut().intern(value))
+    }
+
+    /// The interned text. Cloning it only bumps a reference count.
+    pub fn as_str(self) -> Rc<str> {
+        INTERNER.with(|interner| Rc::clone(&interner.borrow().strings[self.0 as usize]))
+    }
+}
+
+impl std::fmt::Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Symbol({:?})", self.as_str())
+    }
+}
+
+/// Interned strings are never freed; they live as long as the thread.
+struct Interner {
+    symbols: HashMap<Rc<str>, Symbol>,
+    strings: Vec<Rc<str>>,
+}
+
+impl Interner {
+    fn new() -> Self {
+        let mut interner = Interner {
+            symbols: HashMap::new(),
+            strings: Vec::new(),
+        };
+        for value in Symbol::PREDEFINED {
+            interner.intern(value);
+        }
+        interner
+    }
+
+    fn intern(&mut self, value: &str) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(value) {
+            return symbol;
+        }
+
+        let symbol = Symbol(self.strings.len() as u32);
+        let value: Rc<str> = value.into();
+        self.strings.push(Rc::clone(&value));
+        self.symbols.insert(value, symbol);
+        symbol
+    }
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::new());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern() {
+        let a = Symbol::intern("counter");
+
+        assert_eq!(a, Symbol::intern("counter"));
+        assert_ne!(a, Symbol::intern("count"));
+        assert_eq!(Symbol::intern("init"), Symbol::INIT);
+        assert_eq!(Symbol::intern(""), Symbol::EMPTY);
+        assert!(Rc::ptr_eq(&a.as_str(), &Symbol::intern("counter").as_str()));
+        assert_eq!(a.to_string(), "counter");
+    }
+}
@@ -1,11 +1,84 @@
-use crate::{expr::*, token::TokenKind};
+use std::{cell::RefCell, collections::HashMap, io::Write, rc::Rc};
 
-#[derive(Debug, Clone, PartialEq)]
+use crate::{
+    class::{Class, Instance},
+    collector::Collector,
+    environment::{Environment, Globals},
+    expr::*,
+    function::{self, Callable, Function, NativeFunction},
+    intern::Symbol,
+    object::GcStats,
+    stmt::{self, Stmt},
+    token::{Span, Token, TokenKind},
+    vm,
+};
+
+#[derive(Debug, Clone)]
 pub enum Output {
     Number(f64),
     Boolean(bool),
-    String(String),
+    String(Rc<str>),
+    Nil,
+    Function(Rc<Function>),
+    NativeFunction(Rc<NativeFunction>),
+    Class(Rc<Class>),
+    Instance(Rc<Instance>),
+}
+
+impl PartialEq for Output {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Output::Number(l), Output::Number(r)) => l == r,
+            (Output::Boolean(l), Output::Boolean(r)) => l == r,
+            (Output::String(l), Output::String(r)) => Rc::ptr_eq(l, r) || l == r,
+            (Output::Nil, Output::Nil) => true,
+            (Output::Function(l), Output::Function(r)) => Rc::ptr_eq(l, r),
+            (Output::NativeFunction(l), Output::NativeFunction(r)) => Rc::ptr_eq(l, r),
+            (Output::Class(l), Output::Class(r)) => Rc::ptr_eq(l, r),
+            (Output::Instance(l), Output::Instance(r)) => Rc::ptr_eq(l, r),
+            _ => false,
+        }
+    }
+}
+
+/// The runtime type of an `Output`, as named in error messages.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueType {
+    Number,
+    Boolean,
+    String,
     Nil,
+    Function,
+    Class,
+    Instance,
+}
+
+impl std::fmt::Display for ValueType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValueType::Number => write!(f, "number"),
+            ValueType::Boolean => write!(f, "boolean"),
+            ValueType::String => write!(f, "string"),
+            ValueType::Nil => write!(f, "nil"),
+            ValueType::Function => write!(f, "function"),
+            ValueType::Class => write!(f, "class"),
+            ValueType::Instance => write!(f, "instance"),
+        }
+    }
+}
+
+impl Output {
+    pub fn type_of(&self) -> ValueType {
+        match self {
+            Output::Number(_) => ValueType::Number,
+            Output::Boolean(_) => ValueType::Boolean,
+            Output::String(_) => ValueType::String,
+            Output::Nil => ValueType::Nil,
+            Output::Function(_) | Output::NativeFunction(_) => ValueType::Function,
+            Output::Class(_) => ValueType::Class,
+            Output::Instance(_) => ValueType::Instance,
+        }
+    }
 }
 
 impl From<Output> for bool {
@@ -18,120 +91,583 @@ impl From<Output> for bool {
     }
 }
 
+impl std::fmt::Display for Output {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Output::Number(v) => write!(f, "{}", v),
+            Output::Boolean(v) => write!(f, "{}", v),
+            Output::String(v) => write!(f, "{}", v),
+            Output::Nil => write!(f, "nil"),
+            Output::Function(v) => write!(f, "<fn {}>", v.name()),
+            Output::NativeFunction(_) => write!(f, "<native fn>"),
+            Output::Class(v) => write!(f, "{}", v.name()),
+            Output::Instance(v) => write!(f, "<{} instance>", v.class_name()),
+        }
+    }
+}
+
+#[derive(Debug)]
 pub enum RuntimeError {
-    InvalidOperand,
-    DivisionByZero,
-    InvalidOperation,
+    InvalidOperand(Token, ValueType),
+    InvalidOperands(Token, ValueType, ValueType),
+    DivisionByZero(Token),
+    InvalidCondition(Token, ValueType),
+    UndefinedVariable(String, Span),
+    NotCallable(Span),
+    ArityMismatch(usize, usize, Span),
+    NotAnInstance(Span),
+    UndefinedProperty(String, Span),
+    SuperclassNotClass(Span),
+    StackOverflow(Span),
+}
+
+impl RuntimeError {
+    pub fn span(&self) -> Span {
+        match self {
+            RuntimeError::InvalidOperand(operator, _)
+            | RuntimeError::InvalidOperands(operator, _, _)
+            | RuntimeError::DivisionByZero(operator)
+            | RuntimeError::InvalidCondition(operator, _) => operator.span,
+            RuntimeError::UndefinedVariable(_, span)
+            | RuntimeError::NotCallable(span)
+            | RuntimeError::ArityMismatch(_, _, span)
+            | RuntimeError::NotAnInstance(span)
+            | RuntimeError::UndefinedProperty(_, span)
+            | RuntimeError::SuperclassNotClass(span)
+            | RuntimeError::StackOverflow(span) => *span,
+        }
+    }
 }
 
-pub struct Interpreter;
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuntimeError::InvalidOperand(operator, operand) => write!(
+                f,
+                "Operand of '{}' must be a number, got {}",
+                operator, operand
+            ),
+            RuntimeError::InvalidOperands(operator, left, right) => {
+                let expected = match operator.kind {
+                    TokenKind::Plus => "two numbers or two strings",
+                    _ => "numbers",
+                };
+                write!(
+                    f,
+                    "Operands of '{}' must be {}, got {} and {}",
+                    operator, expected, left, right
+                )
+            }
+            RuntimeError::DivisionByZero(_) => write!(f, "Division by zero"),
+            RuntimeError::InvalidCondition(operator, condition) => write!(
+                f,
+                "Condition of '{}' must be a boolean, got {}",
+                operator, condition
+            ),
+            RuntimeError::UndefinedVariable(name, _) => {
+                write!(f, "Undefined variable '{}'", name)
+            }
+            RuntimeError::NotCallable(_) => write!(f, "Can only call functions and classes"),
+            RuntimeError::ArityMismatch(expected, got, _) => {
+                write!(f, "Expected {} arguments but got {}", expected, got)
+            }
+            RuntimeError::NotAnInstance(_) => write!(f, "Only instances have properties"),
+            RuntimeError::UndefinedProperty(name, _) => {
+                write!(f, "Undefined property '{}'", name)
+            }
+            RuntimeError::SuperclassNotClass(_) => write!(f, "Superclass must be a class"),
+            RuntimeError::StackOverflow(_) => write!(f, "Stack overflow"),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
 
-impl<'a> Interpreter {
+/// Non-local exits out of statement execution: either a runtime error or a
+/// `return` travelling up to the enclosing function call.
+#[derive(Debug)]
+pub enum Unwind {
+    Error(RuntimeError),
+    Return(Output),
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(value: RuntimeError) -> Self {
+        Unwind::Error(value)
+    }
+}
+
+pub struct Interpreter {
+    globals: Globals,
+    environment: Option<Rc<RefCell<Environment>>>,
+    out: Box<dyn Write>,
+    /// Lox calls currently in progress, capped like the VM's call frames.
+    depth: usize,
+    collector: Collector,
+}
+
+impl Drop for Interpreter {
+    /// Frees the cycles that globals and the current scope kept alive.
+    fn drop(&mut self) {
+        drop(std::mem::take(&mut self.globals));
+        self.environment = None;
+        self.collect_garbage();
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
     pub fn new() -> Self {
-        Interpreter {}
+        Self::with_output(Box::new(std::io::stdout()))
+    }
+
+    /// An interpreter that writes `print` output to `out` instead of stdout.
+    pub fn with_output(out: Box<dyn Write>) -> Self {
+        let mut globals = Globals::new();
+        globals.define(
+            Symbol::intern("clock"),
+            Output::NativeFunction(Rc::new(NativeFunction::new("clock", 0, function::clock))),
+        );
+
+        Interpreter {
+            globals,
+            environment: None,
+            out,
+            depth: 0,
+            collector: Collector::new(),
+        }
+    }
+
+    pub fn set_gc_stress(&mut self, stress: bool) {
+        self.collector.set_stress(stress);
+    }
+
+    pub fn gc_stats(&self) -> GcStats {
+        self.collector.stats()
+    }
+
+    /// Frees every cycle of objects the program can no longer reach.
+    pub fn collect_garbage(&mut self) {
+        self.collector.collect();
+    }
+
+    /// Moves `environment` behind an `Rc` where the collector can see it.
+    pub fn new_environment(&mut self, environment: Environment) -> Rc<RefCell<Environment>> {
+        let environment = Rc::new(RefCell::new(environment));
+        self.collector.track_environment(&environment);
+        environment
+    }
+
+    pub fn new_instance(&mut self, class: Rc<Class>) -> Rc<Instance> {
+        let instance = Rc::new(Instance::new(class));
+        self.collector.track_instance(&instance);
+        instance
+    }
+
+    pub fn execute(&mut self, statements: &[Stmt]) -> Result<(), RuntimeError> {
+        match self.execute_statements(statements) {
+            Ok(()) | Err(Unwind::Return(_)) => Ok(()),
+            Err(Unwind::Error(e)) => Err(e),
+        }
+    }
+
+    fn execute_statements(&mut self, statements: &[Stmt]) -> Result<(), Unwind> {
+        for statement in statements {
+            self.execute_statement(statement)?;
+        }
+
+        Ok(())
+    }
+
+    fn execute_statement(&mut self, statement: &Stmt) -> Result<(), Unwind> {
+        match statement {
+            Stmt::Expression(v) => self.execute_expression(v),
+            Stmt::Print(v) => self.execute_print(v),
+            Stmt::Var(v) => self.execute_var(v),
+            Stmt::Block(v) => {
+                let environment = Environment::new(self.environment.clone());
+                self.execute_block(&v.statements, environment)
+            }
+            Stmt::If(v) => self.execute_if(v),
+            Stmt::While(v) => self.execute_while(v),
+            Stmt::Function(v) => self.execute_function(v),
+            Stmt::Return(v) => self.execute_return(v),
+            Stmt::Class(v) => self.execute_class(v),
+        }
     }
 
-    pub fn interpret(&mut self, expr: Expr<'a>) -> Result<Output, RuntimeError> {
+    fn execute_class(&mut self, statement: &stmt::Class) -> Result<(), Unwind> {
+        let superclass = match &statement.superclass {
+            Some(superclass) => {
+                let span = superclass.name.span;
+                match self.evaluate_variable(superclass)? {
+                    Output::Class(v) => Some(v),
+                    _ => return Err(RuntimeError::SuperclassNotClass(span).into()),
+                }
+            }
+            None => None,
+        };
+
+        let previous = self.environment.clone();
+        if let Some(superclass) = &superclass {
+            let mut environment = Environment::new(self.environment.clone());
+            environment.define(Output::Class(Rc::clone(superclass)));
+            self.environment = Some(self.new_environment(environment));
+        }
+
+        let mut methods = HashMap::new();
+        for method in &statement.methods {
+            let method_name = method.name.symbol();
+            let is_initializer = method_name == Symbol::INIT;
+            let function =
+                Function::new(Rc::clone(method), self.environment.clone(), is_initializer);
+            methods.insert(method_name, Rc::new(function));
+        }
+
+        self.environment = previous;
+
+        let class = Class::new(statement.name.to_string(), superclass, methods);
+        self.define(&statement.name, Output::Class(Rc::new(class)));
+        Ok(())
+    }
+
+    fn execute_expression(&mut self, statement: &stmt::Expression) -> Result<(), Unwind> {
+        self.interpret(&statement.expr)?;
+        Ok(())
+    }
+
+    fn execute_print(&mut self, statement: &stmt::Print) -> Result<(), Unwind> {
+        let value = self.interpret(&statement.expr)?;
+        writeln!(self.out, "{}", value).expect("failed to write output");
+        Ok(())
+    }
+
+    fn execute_var(&mut self, statement: &stmt::Var) -> Result<(), Unwind> {
+        let value = match &statement.initializer {
+            Some(initializer) => self.interpret(initializer)?,
+            None => Output::Nil,
+        };
+
+        self.define(&statement.name, value);
+        Ok(())
+    }
+
+    fn execute_if(&mut self, statement: &stmt::If) -> Result<(), Unwind> {
+        let condition = self.interpret(&statement.condition)?;
+
+        if bool::from(condition) {
+            self.execute_statement(&statement.then_branch)
+        } else if let Some(else_branch) = &statement.else_branch {
+            self.execute_statement(else_branch)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn execute_while(&mut self, statement: &stmt::While) -> Result<(), Unwind> {
+        while bool::from(self.interpret(&statement.condition)?) {
+            self.execute_statement(&statement.body)?;
+        }
+
+        Ok(())
+    }
+
+    fn execute_function(&mut self, statement: &Rc<stmt::Function>) -> Result<(), Unwind> {
+        let function = Function::new(Rc::clone(statement), self.environment.clone(), false);
+
+        self.define(&statement.name, Output::Function(Rc::new(function)));
+        Ok(())
+    }
+
+    fn execute_return(&mut self, statement: &stmt::Return) -> Result<(), Unwind> {
+        let value = match &statement.value {
+            Some(value) => self.interpret(value)?,
+            None => Output::Nil,
+        };
+
+        Err(Unwind::Return(value))
+    }
+
+    pub fn execute_block(
+        &mut self,
+        statements: &[Stmt],
+        environment: Environment,
+    ) -> Result<(), Unwind> {
+        let environment = self.new_environment(environment);
+        let previous = self.environment.replace(environment);
+
+        let result = self.execute_statements(statements);
+
+        self.environment = previous;
+        result
+    }
+
+    pub fn interpret(&mut self, expr: &Expr) -> Result<Output, RuntimeError> {
         match expr {
             Expr::Literal(v) => self.evaluate_literal(v),
             Expr::Binary(v) => self.evaluate_binary(v),
             Expr::Unary(v) => self.evaluate_unary(v),
             Expr::Grouping(v) => self.evaluate_grouping(v),
             Expr::Ternary(v) => self.evaluate_ternary(v),
+            Expr::Variable(v) => self.evaluate_variable(v),
+            Expr::Assign(v) => self.evaluate_assign(v),
+            Expr::Logical(v) => self.evaluate_logical(v),
+            Expr::Call(v) => self.evaluate_call(v),
+            Expr::Get(v) => self.evaluate_get(v),
+            Expr::Set(v) => self.evaluate_set(v),
+            Expr::This(v) => self.look_up(&v.keyword, v.binding),
+            Expr::Super(v) => self.evaluate_super(v),
+            Expr::Interpolation(v) => self.evaluate_interpolation(v),
         }
     }
 
-    fn evaluate_literal(&mut self, expr: Literal<'a>) -> Result<Output, RuntimeError> {
+    fn evaluate_get(&mut self, expr: &Get) -> Result<Output, RuntimeError> {
+        match self.interpret(&expr.object)? {
+            Output::Instance(instance) => Instance::get(&instance, &expr.name),
+            _ => Err(RuntimeError::NotAnInstance(expr.name.span)),
+        }
+    }
+
+    fn evaluate_set(&mut self, expr: &Set) -> Result<Output, RuntimeError> {
+        let Output::Instance(instance) = self.interpret(&expr.object)? else {
+            return Err(RuntimeError::NotAnInstance(expr.name.span));
+        };
+
+        let value = self.interpret(&expr.value)?;
+        instance.set(&expr.name, value.clone());
+        Ok(value)
+    }
+
+    fn evaluate_super(&mut self, expr: &Super) -> Result<Output, RuntimeError> {
+        let binding = expr
+            .binding
+            .expect("'super' is always resolved to a local scope");
+
+        let superclass = self.look_up(&expr.keyword, expr.binding)?;
+        let this = self.look_up(
+            &expr.keyword,
+            Some(Binding {
+                depth: binding.depth - 1,
+                slot: 0,
+            }),
+        )?;
+
+        let (Output::Class(superclass), Output::Instance(instance)) = (superclass, this) else {
+            unreachable!("'super' is only defined inside subclass methods");
+        };
+
+        match superclass.find_method(expr.method.symbol()) {
+            Some(method) => Ok(Output::Function(Rc::new(method.bind(instance)))),
+            None => Err(RuntimeError::UndefinedProperty(
+                expr.method.to_string(),
+                expr.method.span,
+            )),
+        }
+    }
+
+    fn evaluate_call(&mut self, expr: &Call) -> Result<Output, RuntimeError> {
+        let callee = self.interpret(&expr.callee)?;
+
+        let mut arguments = Vec::with_capacity(expr.arguments.len());
+        for argument in &expr.arguments {
+            arguments.push(self.interpret(argument)?);
+        }
+
+        let callable: &dyn Callable = match &callee {
+            Output::Function(v) => v.as_ref(),
+            Output::NativeFunction(v) => v.as_ref(),
+            Output::Class(v) => v,
+            _ => return Err(RuntimeError::NotCallable(expr.paren.span)),
+        };
+
+        if arguments.len() != callable.arity() {
+            return Err(RuntimeError::ArityMismatch(
+                callable.arity(),
+                arguments.len(),
+                expr.paren.span,
+            ));
+        }
+
+        if matches!(callee, Output::NativeFunction(_)) {
+            return callable.call(self, arguments);
+        }
+
+        // The VM counts the top-level script as a frame too.
+        if self.depth + 1 == vm::FRAMES_MAX {
+            return Err(RuntimeError::StackOverflow(expr.paren.span));
+        }
+
+        self.depth += 1;
+        let result = callable.call(self, arguments);
+        self.depth -= 1;
+        result
+    }
+
+    fn evaluate_logical(&mut self, expr: &Logical) -> Result<Output, RuntimeError> {
+        let left = self.interpret(&expr.left)?;
+        let is_truthy = bool::from(left.clone());
+
+        match expr.operator.kind {
+            TokenKind::Or if is_truthy => Ok(left),
+            TokenKind::And if !is_truthy => Ok(left),
+            TokenKind::Or | TokenKind::And => self.interpret(&expr.right),
+            _ => unreachable!("Invalid token"),
+        }
+    }
+
+    fn evaluate_variable(&mut self, expr: &Variable) -> Result<Output, RuntimeError> {
+        self.look_up(&expr.name, expr.binding)
+    }
+
+    fn evaluate_assign(&mut self, expr: &Assign) -> Result<Output, RuntimeError> {
+        let value = self.interpret(&expr.value)?;
+
+        match (expr.binding, &self.environment) {
+            (Some(binding), Some(environment)) => {
+                environment
+                    .borrow_mut()
+                    .assign_at(binding.depth, binding.slot, value.clone());
+            }
+            _ => self.globals.assign(&expr.name, value.clone())?,
+        }
+
+        Ok(value)
+    }
+
+    fn look_up(&self, name: &Token, binding: Option<Binding>) -> Result<Output, RuntimeError> {
+        match (binding, &self.environment) {
+            (Some(binding), Some(environment)) => {
+                Ok(environment.borrow().get_at(binding.depth, binding.slot))
+            }
+            _ => self.globals.get(name),
+        }
+    }
+
+    fn define(&mut self, name: &Token, value: Output) {
+        match &self.environment {
+            Some(environment) => {
+                environment.borrow_mut().define(value);
+            }
+            None => self.globals.define(name.symbol(), value),
+        }
+    }
+
+    fn evaluate_literal(&mut self, expr: &Literal) -> Result<Output, RuntimeError> {
         let expr = match expr.value {
             LiteralValue::Number(v) => Output::Number(v),
             LiteralValue::Boolean(v) => Output::Boolean(v),
-            LiteralValue::String(v) => Output::String(v.to_owned()),
+            LiteralValue::String(v) => Output::String(v.as_str()),
             LiteralValue::Nil => Output::Nil,
         };
 
         Ok(expr)
     }
 
-    fn evaluate_grouping(&mut self, expr: Grouping<'a>) -> Result<Output, RuntimeError> {
-        self.interpret(*expr.expr)
+    fn evaluate_grouping(&mut self, expr: &Grouping) -> Result<Output, RuntimeError> {
+        self.interpret(&expr.expr)
     }
 
-    fn evaluate_unary(&mut self, expr: Unary<'a>) -> Result<Output, RuntimeError> {
-        let right = self.interpret(*expr.right)?;
+    fn evaluate_unary(&mut self, expr: &Unary) -> Result<Output, RuntimeError> {
+        let right = self.interpret(&expr.right)?;
 
         let is_truthy = bool::from(right.clone());
 
         match expr.operator.kind {
             TokenKind::Minus => match right {
                 Output::Number(v) => Ok(Output::Number(-v)),
-                _ => Err(RuntimeError::InvalidOperand),
+                other => Err(RuntimeError::InvalidOperand(expr.operator, other.type_of())),
             },
             TokenKind::Bang => Ok(Output::Boolean(!is_truthy)),
             _ => unreachable!("Invalid token"),
         }
     }
 
-    fn evaluate_binary(&mut self, expr: Binary<'a>) -> Result<Output, RuntimeError> {
-        let left = self.interpret(*expr.left)?;
-        let right = self.interpret(*expr.right)?;
+    fn evaluate_binary(&mut self, expr: &Binary) -> Result<Output, RuntimeError> {
+        let left = self.interpret(&expr.left)?;
+        let right = self.interpret(&expr.right)?;
+        let operator = expr.operator;
 
-        match expr.operator.kind {
-            TokenKind::Minus => match (left, right) {
-                (Output::Number(l), Output::Number(r)) => Ok(Output::Number(l + r)),
-                _ => Err(RuntimeError::InvalidOperand),
-            },
-            TokenKind::Slash => match (left, right) {
-                (Output::Number(l), Output::Number(r)) => {
-                    if r == 0.0 {
-                        Err(RuntimeError::DivisionByZero)
-                    } else {
-                        Ok(Output::Number(l / r))
-                    }
+        match operator.kind {
+            TokenKind::Comma => Ok(right),
+            TokenKind::Minus => {
+                let (l, r) = number_operands(operator, &left, &right)?;
+                Ok(Output::Number(l - r))
+            }
+            TokenKind::Slash => {
+                let (l, r) = number_operands(operator, &left, &right)?;
+                if r == 0.0 {
+                    Err(RuntimeError::DivisionByZero(operator))
+                } else {
+                    Ok(Output::Number(l / r))
                 }
-                _ => Err(RuntimeError::InvalidOperand),
-            },
-            TokenKind::Star => match (left, right) {
-                (Output::Number(l), Output::Number(r)) => Ok(Output::Number(l * r)),
-                _ => Err(RuntimeError::InvalidOperand),
-            },
+            }
+            TokenKind::Star => {
+                let (l, r) = number_operands(operator, &left, &right)?;
+                Ok(Output::Number(l * r))
+            }
             TokenKind::Plus => match (left, right) {
                 (Output::Number(l), Output::Number(r)) => Ok(Output::Number(l + r)),
-                (Output::String(l), Output::String(r)) => Ok(Output::String(format!("{}{}", l, r))),
-                _ => Err(RuntimeError::InvalidOperand),
-            },
-            TokenKind::Greater => match (left, right) {
-                (Output::Number(l), Output::Number(r)) => Ok(Output::Boolean(l > r)),
-                _ => Err(RuntimeError::InvalidOperand),
-            },
-            TokenKind::GreaterEqual => match (left, right) {
-                (Output::Number(l), Output::Number(r)) => Ok(Output::Boolean(l >= r)),
-                _ => Err(RuntimeError::InvalidOperand),
-            },
-            TokenKind::Less => match (left, right) {
-                (Output::Number(l), Output::Number(r)) => Ok(Output::Boolean(l < r)),
-                _ => Err(RuntimeError::InvalidOperand),
-            },
-            TokenKind::LessEqual => match (left, right) {
-                (Output::Number(l), Output::Number(r)) => Ok(Output::Boolean(l <= r)),
-                _ => Err(RuntimeError::InvalidOperand),
+                (Output::String(l), Output::String(r)) => {
+                    Ok(Output::String(format!("{}{}", l, r).into()))
+                }
+                (l, r) => Err(RuntimeError::InvalidOperands(
+                    operator,
+                    l.type_of(),
+                    r.type_of(),
+                )),
             },
+            TokenKind::Greater => {
+                let (l, r) = number_operands(operator, &left, &right)?;
+                Ok(Output::Boolean(l > r))
+            }
+            TokenKind::GreaterEqual => {
+                let (l, r) = number_operands(operator, &left, &right)?;
+                Ok(Output::Boolean(l >= r))
+            }
+            TokenKind::Less => {
+                let (l, r) = number_operands(operator, &left, &right)?;
+                Ok(Output::Boolean(l < r))
+            }
+            TokenKind::LessEqual => {
+                let (l, r) = number_operands(operator, &left, &right)?;
+                Ok(Output::Boolean(l <= r))
+            }
             TokenKind::BangEqual => Ok(Output::Boolean(left != right)),
             TokenKind::EqualEqual => Ok(Output::Boolean(left == right)),
             _ => unreachable!("Unreachable code"),
         }
     }
 
-    fn evaluate_ternary(&mut self, expr: Ternary<'a>) -> Result<Output, RuntimeError> {
-        let left = self.interpret(*expr.left)?;
-        let middle = self.interpret(*expr.middle)?;
-        let right = self.interpret(*expr.right)?;
+    /// Joins the parts of an interpolated string, each formatted the way
+    /// `print` would show it.
+    fn evaluate_interpolation(&mut self, expr: &Interpolation) -> Result<Output, RuntimeError> {
+        let mut text = String::new();
+        for part in &expr.parts {
+            text.push_str(&self.interpret(part)?.to_string());
+        }
+
+        Ok(Output::String(text.into()))
+    }
+
+    fn evaluate_ternary(&mut self, expr: &Ternary) -> Result<Output, RuntimeError> {
+        let left = self.interpret(&expr.left)?;
+        let middle = self.interpret(&expr.middle)?;
+        let right = self.interpret(&expr.right)?;
 
         match expr.left_operator.kind {
             TokenKind::QuestionMark => match expr.right_operator.kind {
                 TokenKind::Colon => match left {
                     Output::Boolean(true) => Ok(middle),
                     Output::Boolean(false) => Ok(right),
-                    _ => Err(RuntimeError::InvalidOperation),
+                    other => Err(RuntimeError::InvalidCondition(
+                        expr.left_operator,
+                        other.type_of(),
+                    )),
                 },
                 _ => unreachable!("Unreachable code"),
             },
@@ -139,3 +675,249 @@ impl<'a> Interpreter {
         }
     }
 }
+
+fn number_operands(
+    operator: Token,
+    left: &Output,
+    right: &Output,
+) -> Result<(f64, f64), RuntimeError> {
+    match (left, right) {
+        (Output::Number(l), Output::Number(r)) => Ok((*l, *r)),
+        _ => Err(RuntimeError::InvalidOperands(
+            operator,
+            left.type_of(),
+            right.type_of(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{lexer::Lexer, parser::Parser, resolver::Resolver};
+
+    use super::*;
+
+    fn evaluate(src: &str) -> Result<Output, RuntimeError> {
+        let mut parser = Parser::new(Lexer::new(src));
+        Interpreter::new().interpret(&parser.parse().unwrap())
+    }
+
+    fn parse(src: &str) -> Vec<Stmt> {
+        let (mut statements, errors) = Parser::new(Lexer::new(src)).parse_program();
+        assert!(errors.is_empty());
+        Resolver::new().resolve(&mut statements).unwrap();
+        statements
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(evaluate("(1 + 2) * 3 - 4").unwrap(), Output::Number(5.0));
+        assert_eq!(evaluate("1, 2").unwrap(), Output::Number(2.0));
+        assert!(matches!(
+            evaluate("1 / 0"),
+            Err(RuntimeError::DivisionByZero(_))
+        ));
+    }
+
+    #[test]
+    fn test_operand_errors() {
+        let error = evaluate("\"a\" - nil").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Operands of '-' must be numbers, got string and nil"
+        );
+        assert_eq!(error.span(), Span::new(4, 5));
+
+        assert_eq!(
+            evaluate("1 + \"a\"").unwrap_err().to_string(),
+            "Operands of '+' must be two numbers or two strings, got number and string"
+        );
+        assert_eq!(
+            evaluate("-true").unwrap_err().to_string(),
+            "Operand of '-' must be a number, got boolean"
+        );
+        assert_eq!(
+            evaluate("1 ? 2 : 3").unwrap_err().to_string(),
+            "Condition of '?' must be a boolean, got number"
+        );
+    }
+
+    #[test]
+    fn test_scopes() {
+        let src = "var a = 1; var b = 2; { var a = 10; b = a + b; } a = a + b;";
+        let statements = parse(src);
+
+        let mut interpreter = Interpreter::new();
+        interpreter.execute(&statements).unwrap();
+
+        let a = interpreter.interpret(&Parser::new(Lexer::new("a")).parse().unwrap());
+        assert_eq!(a.unwrap(), Output::Number(13.0));
+    }
+
+    #[test]
+    fn test_undefined_variable() {
+        let statements = parse("{ var a = 1; } a = 2;");
+
+        assert!(matches!(
+            Interpreter::new().execute(&statements),
+            Err(RuntimeError::UndefinedVariable(name, _)) if name == "a"
+        ));
+    }
+
+    #[test]
+    fn test_logical_returns_operand() {
+        assert_eq!(
+            evaluate("nil or \"yes\"").unwrap(),
+            Output::String("yes".into())
+        );
+        assert_eq!(evaluate("0 and nil").unwrap(), Output::Nil);
+        assert_eq!(
+            evaluate("false and undefined").unwrap(),
+            Output::Boolean(false)
+        );
+    }
+
+    #[test]
+    fn test_string_literals_are_shared() {
+        let (Ok(Output::String(a)), Ok(Output::String(b))) =
+            (evaluate("\"shared\""), evaluate("\"shared\""))
+        else {
+            panic!("expected strings");
+        };
+
+        assert!(Rc::ptr_eq(&a, &b));
+        assert_eq!(
+            evaluate("\"a\" + \"b\" == \"ab\"").unwrap(),
+            Output::Boolean(true)
+        );
+    }
+
+    #[test]
+    fn test_evaluates_by_reference() {
+        let mut interpreter = Interpreter::new();
+        interpreter.execute(&parse("var n = 0;")).unwrap();
+
+        let expr = Parser::new(Lexer::new("n = n + 1")).parse().unwrap();
+        interpreter.interpret(&expr).unwrap();
+        assert_eq!(interpreter.interpret(&expr).unwrap(), Output::Number(2.0));
+
+        // The function keeps its declaration alive after the tree is dropped.
+        let statements = parse("fun twice(x) { return x * 2; }");
+        interpreter.execute(&statements).unwrap();
+        drop(statements);
+
+        let call = Parser::new(Lexer::new("twice(n)")).parse().unwrap();
+        assert_eq!(interpreter.interpret(&call).unwrap(), Output::Number(4.0));
+    }
+
+    #[test]
+    fn test_loops() {
+        let src = "var sum = 0; for (var i = 1; i <= 4; i = i + 1) { if (i == 3) sum = sum + 100; else sum = sum + i; } var n = 0; while (n < 5) n = n + 1;";
+        let statements = parse(src);
+
+        let mut interpreter = Interpreter::new();
+        interpreter.execute(&statements).unwrap();
+
+        let result = interpreter.interpret(&Parser::new(Lexer::new("sum + n")).parse().unwrap());
+        assert_eq!(result.unwrap(), Output::Number(112.0));
+    }
+
+    #[test]
+    fn test_closures() {
+        let src = "fun counter() { var i = 0; fun count() { i = i + 1; return i; } return count; } var c = counter(); c(); var x = c();";
+        let statements = parse(src);
+
+        let mut interpreter = Interpreter::new();
+        interpreter.execute(&statements).unwrap();
+
+        let x = interpreter.interpret(&Parser::new(Lexer::new("x")).parse().unwrap());
+        assert_eq!(x.unwrap(), Output::Number(2.0));
+    }
+
+    #[test]
+    fn test_deep_recursion() {
+        let src = "fun f(n) { if (n == 0) return 0; return 1 + f(n - 1); } var x = f(4000);";
+        let mut interpreter = Interpreter::new();
+        interpreter.execute(&parse(src)).unwrap();
+
+        let x = interpreter.interpret(&Parser::new(Lexer::new("x")).parse().unwrap());
+        assert_eq!(x.unwrap(), Output::Number(4000.0));
+    }
+
+    #[test]
+    fn test_call_errors() {
+        assert!(matches!(evaluate("1()"), Err(RuntimeError::NotCallable(_))));
+        assert!(matches!(
+            evaluate("clock(1)"),
+            Err(RuntimeError::ArityMismatch(0, 1, _))
+        ));
+    }
+
+    #[test]
+    fn test_classes() {
+        let src = "class A { init(n) { this.n = n; } get() { return this.n; } } class B < A { init() { super.init(40); } get() { return super.get() + 2; } } var b = B(); var m = b.get; var x = m();";
+        let statements = parse(src);
+
+        let mut interpreter = Interpreter::new();
+        interpreter.execute(&statements).unwrap();
+
+        let x = interpreter.interpret(&Parser::new(Lexer::new("x")).parse().unwrap());
+        assert_eq!(x.unwrap(), Output::Number(42.0));
+
+        let b = interpreter.interpret(&Parser::new(Lexer::new("b")).parse().unwrap());
+        assert_eq!(b.unwrap().to_string(), "<B instance>");
+    }
+
+    #[test]
+    fn test_cycles_are_collected() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .execute(&parse(
+                "class Node { get() { return this; } } \
+                 var n = Node(); n.next = n; n.get = n.get; \
+                 { var m = Node(); m.next = m; }",
+            ))
+            .unwrap();
+        interpreter.collect_garbage();
+        assert_eq!(interpreter.gc_stats().objects_freed, 1);
+
+        let n = match interpreter.interpret(&Parser::new(Lexer::new("n")).parse().unwrap()) {
+            Ok(Output::Instance(n)) => Rc::downgrade(&n),
+            _ => panic!("expected an instance"),
+        };
+        drop(interpreter);
+
+        assert!(n.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_property_errors() {
+        let statements = parse("class A {} A().missing;");
+
+        assert!(matches!(
+            Interpreter::new().execute(&statements),
+            Err(RuntimeError::UndefinedProperty(name, _)) if name == "missing"
+        ));
+        assert!(matches!(
+            evaluate("1.5.x"),
+            Err(RuntimeError::NotAnInstance(_))
+        ));
+    }
+
+    #[test]
+    fn test_resolved_closure_binding() {
+        let src = "var a = \"global\"; var x; var y; { fun show() { return a; } x = show(); var a = \"block\"; y = show(); }";
+        let mut interpreter = Interpreter::new();
+        interpreter.execute(&parse(src)).unwrap();
+
+        let result = interpreter.interpret(&Parser::new(Lexer::new("x == y")).parse().unwrap());
+        assert_eq!(result.unwrap(), Output::Boolean(true));
+    }
+
+    #[test]
+    fn test_execute() {
+        let statements = parse("print \"a\" + \"b\"; 1 + 2;");
+
+        assert!(Interpreter::new().execute(&statements).is_ok());
+    }
+}
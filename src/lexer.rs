@@ -1,19 +1,74 @@
-use crate::token::*;
-use std::collections::HashMap;
+use crate::{intern::Symbol, token::*};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet, VecDeque},
+};
+use unicode_xid::UnicodeXID;
 use TokenKind::*;
 
+/// A problem found while scanning. The lexer reports it as an `Error` token
+/// and carries on with the next character.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LexError {
+    UnexpectedCharacter(char, Span),
+    UnterminatedString(Span),
+    UnterminatedComment(Span),
+    MalformedNumber(Span),
+    InvalidEscape(Span),
+    InvalidUnicodeEscape(Span),
+}
+
+impl LexError {
+    pub fn span(&self) -> Span {
+        match self {
+            LexError::UnexpectedCharacter(_, span)
+            | LexError::UnterminatedString(span)
+            | LexError::UnterminatedComment(span)
+            | LexError::MalformedNumber(span)
+            | LexError::InvalidEscape(span)
+            | LexError::InvalidUnicodeEscape(span) => *span,
+        }
+    }
+}
+
+impl std::fmt::Display for LexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LexError::UnexpectedCharacter(ch, _) => write!(f, "Unexpected character {:?}", ch),
+            LexError::UnterminatedString(_) => write!(f, "Unterminated string"),
+            LexError::UnterminatedComment(_) => write!(f, "Unterminated block comment"),
+            LexError::MalformedNumber(_) => write!(f, "Malformed number"),
+            LexError::InvalidEscape(_) => write!(f, "Invalid escape sequence"),
+            LexError::InvalidUnicodeEscape(_) => {
+                write!(f, "Invalid unicode escape, expected \\u{{XXXX}}")
+            }
+        }
+    }
+}
+
 pub struct Lexer<'a> {
     src: &'a str,
     chars: Vec<(usize, char)>,
     start: usize,
     current: usize,
-    end: usize,
     line: usize,
-    keywords: HashMap<&'a str, TokenKind<'a>>,
+    source: SourceId,
+    keywords: HashMap<&'a str, TokenKind>,
+    /// Further errors found in the last token, returned before scanning on.
+    pending: VecDeque<LexError>,
+    /// One entry per `${` still open, counting the braces opened inside it
+    /// so the `}` that resumes the string can be told apart.
+    interpolations: Vec<usize>,
+    finished: bool,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(src: &'a str) -> Self {
+        Self::with_source(src, SourceId::default())
+    }
+
+    /// A lexer whose token spans point into `source`.
+    pub fn with_source(src: &'a str, source: SourceId) -> Self {
         let keywords = Self::create_keyword_map();
 
         Lexer {
@@ -21,13 +76,16 @@ impl<'a> Lexer<'a> {
             chars: src.char_indices().collect(),
             start: 0,
             current: 0,
-            end: 0,
             line: 0,
+            source,
             keywords,
+            pending: VecDeque::new(),
+            interpolations: Vec::new(),
+            finished: false,
         }
     }
 
-    fn create_keyword_map() -> HashMap<&'a str, TokenKind<'a>> {
+    fn create_keyword_map() -> HashMap<&'a str, TokenKind> {
         let mut keywords = HashMap::new();
 
         keywords.insert("and", And);
@@ -50,27 +108,49 @@ impl<'a> Lexer<'a> {
         keywords
     }
 
-    fn scan_token(&mut self) -> Option<Token<'a>> {
+    fn scan_token(&mut self) -> Option<Token> {
+        if let Some(error) = self.pending.pop_front() {
+            return Some(Token::new(Error(error), error.span()));
+        }
+
+        if self.finished {
+            return None;
+        }
+
         let kind = self.scan_token_kind();
-        let span = Span::new(self.start, self.end);
+        let span = self.span();
 
         if kind == Eof {
-            None
-        } else {
-            Some(Token::new(kind, span))
+            self.finished = true;
         }
+
+        Some(Token::new(kind, span))
     }
 
-    fn scan_token_kind(&mut self) -> TokenKind<'a> {
+    fn scan_token_kind(&mut self) -> TokenKind {
         self.start = self.current;
-        self.end = self.current;
 
         let ch = self.advance();
         match ch {
             '(' => LeftParen,
             ')' => RightParen,
-            '{' => LeftBrace,
-            '}' => RightBrace,
+            '{' => {
+                if let Some(depth) = self.interpolations.last_mut() {
+                    *depth += 1;
+                }
+                LeftBrace
+            }
+            '}' => match self.interpolations.last_mut() {
+                Some(0) => {
+                    self.interpolations.pop();
+                    self.string(true)
+                }
+                Some(depth) => {
+                    *depth -= 1;
+                    RightBrace
+                }
+                None => RightBrace,
+            },
             ',' => Comma,
             '.' => Dot,
             '-' => Minus,
@@ -84,32 +164,34 @@ impl<'a> Lexer<'a> {
             '>' => self.take_select('=', GreaterEqual, Greater),
             '<' => self.take_select('=', LessEqual, Less),
             '/' => self.comment_or(Slash),
-            '"' => self.string(),
+            '"' => self.string(false),
             '\n' => {
                 self.line += 1;
                 Skip(ch)
             }
             '\r' | '\t' | ' ' => Skip(ch),
             '\0' => Eof,
-            ch if ch.is_digit(10) => self.take_number(),
-            ch if ch.is_alphanumeric() => self.take_identifier_or_keyword(),
-            _ => Error("a"),
+            ch if ch.is_ascii_digit() => self.take_number(),
+            ch if ch == '_' || ch.is_xid_start() => self.take_identifier_or_keyword(),
+            _ => Error(LexError::UnexpectedCharacter(ch, self.span())),
         }
     }
 
+    /// The span of the token scanned so far.
+    fn span(&self) -> Span {
+        Span::new(self.offset(self.start), self.offset(self.current)).with_source(self.source)
+    }
+
     fn take_select(
         &mut self,
         expected: char,
-        kind_true: TokenKind<'a>,
-        kind_false: TokenKind<'a>,
-    ) -> TokenKind<'a> {
-        let kind = match self.take(expected) {
+        kind_true: TokenKind,
+        kind_false: TokenKind,
+    ) -> TokenKind {
+        match self.take(expected) {
             true => kind_true,
             false => kind_false,
-        };
-
-        self.end = self.current - 1;
-        kind
+        }
     }
 
     fn take(&mut self, expected: char) -> bool {
@@ -125,6 +207,13 @@ impl<'a> Lexer<'a> {
         self.peek(0) == '\0'
     }
 
+    /// Byte offset into `src` of the char at `index`.
+    fn offset(&self, index: usize) -> usize {
+        self.chars
+            .get(index)
+            .map_or(self.src.len(), |(offset, _)| *offset)
+    }
+
     fn peek(&self, offset: i32) -> char {
         self.chars
             .get(self.current + offset as usize)
@@ -140,104 +229,303 @@ impl<'a> Lexer<'a> {
     }
 
     fn advance_by(&mut self, value: i32) {
-        for _ in 0..=value {
+        for _ in 0..value {
             self.advance();
         }
     }
 
-    fn comment_or(&mut self, or: TokenKind<'a>) -> TokenKind<'a> {
+    fn comment_or(&mut self, or: TokenKind) -> TokenKind {
         if self.take('/') {
-            let comment = self.take_single_line_comment();
-            Comment(comment)
+            self.take_single_line_comment();
+            Comment
         } else if self.take('*') {
-            let comment = self.take_multi_line_comment();
-            Comment(comment)
+            self.take_multi_line_comment()
         } else {
             or
         }
     }
 
-    fn string(&mut self) -> TokenKind<'a> {
+    /// Scans string text up to the closing quote or the next `${`.
+    /// `resumed` is set when the text follows an interpolated expression
+    /// rather than an opening quote.
+    fn string(&mut self, resumed: bool) -> TokenKind {
+        let mut value = std::string::String::new();
+
         while self.peek(0) != '"' && !self.is_at_end() {
-            self.advance();
+            if self.peek(0) == '$' && self.peek(1) == '{' {
+                self.advance_by(2);
+                self.interpolations.push(0);
+
+                // Bad escapes are reported after this token rather than in
+                // its place, so the parser still sees the expression start.
+                let value = Symbol::intern(&value);
+                return if resumed {
+                    InterpolationMiddle(value)
+                } else {
+                    InterpolationStart(value)
+                };
+            }
+
+            match self.advance() {
+                '\\' => match self.escape() {
+                    Ok(ch) => value.push(ch),
+                    Err(e) => self.pending.push_back(e),
+                },
+                ch => value.push(ch),
+            }
         }
 
         if self.is_at_end() {
-            return TokenKind::Error("Unterminated String");
+            self.pending.clear();
+            return Error(LexError::UnterminatedString(self.span()));
         }
         self.advance();
-        self.end = self.current;
 
-        let value = &self.src[self.start + 1..self.end - 1];
-        TokenKind::String(value)
+        if resumed {
+            return InterpolationEnd(Symbol::intern(&value));
+        }
+
+        match self.pending.pop_front() {
+            Some(error) => Error(error),
+            None => TokenKind::String(Symbol::intern(&value)),
+        }
     }
 
-    fn take_single_line_comment(&mut self) -> &'a str {
-        while self.peek(0) != '\n' && !self.is_at_end() {
+    /// Reads the rest of an escape sequence whose backslash was just
+    /// consumed.
+    fn escape(&mut self) -> Result<char, LexError> {
+        let start = self.current - 1;
+        let span = |lexer: &Self| {
+            Span::new(lexer.offset(start), lexer.offset(lexer.current)).with_source(lexer.source)
+        };
+
+        let ch = match self.peek(0) {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '\\' => '\\',
+            '"' => '"',
+            '$' => '$',
+            '0' => '\0',
+            'u' => {
+                self.advance();
+                return self
+                    .unicode_escape()
+                    .ok_or_else(|| LexError::InvalidUnicodeEscape(span(self)));
+            }
+            '\0' => return Err(LexError::InvalidEscape(span(self))),
+            _ => {
+                self.advance();
+                return Err(LexError::InvalidEscape(span(self)));
+            }
+        };
+        self.advance();
+
+        Ok(ch)
+    }
+
+    /// Reads the `{XXXX}` of a `\u{XXXX}` escape: one to six hex digits
+    /// naming a Unicode scalar value.
+    fn unicode_escape(&mut self) -> Option<char> {
+        if !self.take('{') {
+            return None;
+        }
+
+        let digits_start = self.current;
+        while self.peek(0).is_ascii_hexdigit() {
             self.advance();
         }
-        self.end = self.current;
+        let digits = &self.src[self.offset(digits_start)..self.offset(self.current)];
+
+        if !self.take('}') || digits.is_empty() || digits.len() > 6 {
+            return None;
+        }
 
-        &self.src[self.start + 2..self.end]
+        u32::from_str_radix(digits, 16)
+            .ok()
+            .and_then(char::from_u32)
     }
 
-    fn take_multi_line_comment(&mut self) -> &'a str {
-        while self.peek(0) != '*' && self.peek(1) != '/' && !self.is_at_end() {
+    fn take_single_line_comment(&mut self) {
+        while self.peek(0) != '\n' && !self.is_at_end() {
+            self.advance();
+        }
+    }
+
+    fn take_multi_line_comment(&mut self) -> TokenKind {
+        while !(self.peek(0) == '*' && self.peek(1) == '/') {
+            if self.is_at_end() {
+                return Error(LexError::UnterminatedComment(self.span()));
+            }
             if self.peek(0) == '\n' {
                 self.line += 1;
             }
             self.advance();
         }
-        self.end = self.current;
         self.advance_by(2);
 
-        &self.src[self.start + 2..self.end]
+        Comment
     }
 
-    fn take_number(&mut self) -> TokenKind<'a> {
-        while self.peek(0).is_digit(10) {
-            self.advance();
+    /// Scans a number: `0x`, `0b` and `0o` integers, or decimals with an
+    /// optional fraction and exponent. Digits may be separated by single
+    /// underscores, as in `1_000_000`.
+    fn take_number(&mut self) -> TokenKind {
+        self.current = self.start;
+
+        let radix = match (self.peek(0), self.peek(1)) {
+            ('0', 'x' | 'X') => 16,
+            ('0', 'o' | 'O') => 8,
+            ('0', 'b' | 'B') => 2,
+            _ => 10,
+        };
+        let value = match radix {
+            10 => self.take_decimal(),
+            _ => {
+                self.advance_by(2);
+                self.take_integer(radix)
+            }
+        };
+
+        // Letters straight after the digits, as in `12px` or `0b102`, are
+        // part of a bad number rather than an identifier.
+        if self.peek(0).is_xid_continue() {
+            while self.peek(0).is_xid_continue() {
+                self.advance();
+            }
+            return Error(LexError::MalformedNumber(self.span()));
         }
 
-        if self.peek(0) == '.' && self.peek(1).is_digit(10) {
-            self.advance();
+        match value {
+            Some(value) => Number(value),
+            None => Error(LexError::MalformedNumber(self.span())),
         }
+    }
 
-        while self.peek(0).is_digit(10) {
-            self.advance();
+    fn take_decimal(&mut self) -> Option<f64> {
+        let mut text = std::string::String::new();
+        self.take_digits(10, &mut text);
+
+        if self.peek(0) == '.' && self.peek(1).is_ascii_digit() {
+            text.push(self.advance());
+            self.take_digits(10, &mut text);
         }
 
-        self.end = self.current - 1;
+        if matches!(self.peek(0), 'e' | 'E') {
+            text.push(self.advance());
+            if matches!(self.peek(0), '+' | '-') {
+                text.push(self.advance());
+            }
+            if !self.take_digits(10, &mut text) {
+                return None;
+            }
+        }
+
+        text.parse().ok()
+    }
+
+    fn take_integer(&mut self, radix: u32) -> Option<f64> {
+        let mut digits = std::string::String::new();
+        if !self.take_digits(radix, &mut digits) {
+            return None;
+        }
+
+        // Accumulate as a float so that long literals lose precision like
+        // decimal ones do instead of overflowing.
+        Some(digits.chars().fold(0.0, |value, digit| {
+            value * radix as f64 + digit.to_digit(radix).unwrap() as f64
+        }))
+    }
 
-        let value = self.src[self.start..=self.end].parse::<f64>().unwrap();
-        Number(value)
+    /// Appends a run of digits in `radix` to `out`, skipping underscores that
+    /// sit between two digits. Returns whether any digit was read.
+    fn take_digits(&mut self, radix: u32, out: &mut std::string::String) -> bool {
+        let mut any = false;
+
+        loop {
+            let ch = self.peek(0);
+            if ch.is_digit(radix) {
+                out.push(ch);
+                any = true;
+            } else if !(ch == '_' && any && self.peek(1).is_digit(radix)) {
+                return any;
+            }
+            self.advance();
+        }
     }
 
-    fn take_identifier_or_keyword(&mut self) -> TokenKind<'a> {
-        while self.peek(0).is_alphanumeric() {
+    /// Scans an identifier as defined by UAX #31: a `_` or XID_Start
+    /// character followed by XID_Continue characters.
+    fn take_identifier_or_keyword(&mut self) -> TokenKind {
+        while self.peek(0).is_xid_continue() {
             self.advance();
         }
 
-        self.end = self.current;
-        let value = &self.src[self.start..self.end];
+        let value = normalize(&self.src[self.offset(self.start)..self.offset(self.current)]);
         self.keywords
-            .get(&value)
-            .clone()
-            .unwrap_or(&Identifier(value))
-            .clone()
+            .get(value.as_ref())
+            .copied()
+            .unwrap_or_else(|| Identifier(Symbol::intern(&value)))
+    }
+}
+
+/// Brings an identifier into NFC, so that a precomposed `é` and an `e`
+/// followed by a combining accent name the same variable.
+#[cfg(feature = "nfc")]
+fn normalize(name: &str) -> Cow<'_, str> {
+    use unicode_normalization::UnicodeNormalization;
+
+    if unicode_normalization::is_nfc(name) {
+        Cow::Borrowed(name)
+    } else {
+        Cow::Owned(name.nfc().collect())
     }
 }
 
+#[cfg(not(feature = "nfc"))]
+fn normalize(name: &str) -> Cow<'_, str> {
+    Cow::Borrowed(name)
+}
+
+/// Finds identifiers that can be mistaken for a different identifier seen
+/// earlier, such as a Cyrillic `а` next to a Latin `a`. Each confusable name
+/// is returned once, paired with the token it looks like.
+pub fn confusable_identifiers(tokens: impl Iterator<Item = Token>) -> Vec<(Token, Token)> {
+    let mut seen: HashMap<std::string::String, Token> = HashMap::new();
+    let mut reported = HashSet::new();
+    let mut confusables = Vec::new();
+
+    for token in tokens {
+        let Identifier(name) = token.kind else {
+            continue;
+        };
+
+        let skeleton = unicode_security::skeleton(&name.as_str()).collect();
+        match seen.get(&skeleton) {
+            Some(first) if first.symbol() != name => {
+                if reported.insert(name) {
+                    confusables.push((*first, token));
+                }
+            }
+            Some(_) => {}
+            None => {
+                seen.insert(skeleton, token);
+            }
+        }
+    }
+
+    confusables
+}
+
 impl<'a> Iterator for Lexer<'a> {
-    type Item = Token<'a>;
+    type Item = Token;
     fn next(&mut self) -> Option<Self::Item> {
         self.scan_token()
     }
 }
 
 #[cfg(test)]
-
-fn print_token<'a>(tokens: impl Iterator<Item = Token<'a>>) {
+fn print_token(tokens: impl Iterator<Item = Token>) {
     println!("{:?}", tokens.collect::<Vec<Token>>());
 }
 
@@ -250,3 +538,167 @@ fn test_lexer() {
 
     print_token(lexer);
 }
+
+#[test]
+fn test_lex_errors() {
+    let errors = |src| {
+        Lexer::new(src)
+            .filter_map(|token| match token.kind {
+                Error(e) => Some(e),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        errors("var a = 1 @ 2 # 3;"),
+        [
+            LexError::UnexpectedCharacter('@', Span::new(10, 11)),
+            LexError::UnexpectedCharacter('#', Span::new(14, 15)),
+        ]
+    );
+    assert_eq!(
+        errors("print 12px;"),
+        [LexError::MalformedNumber(Span::new(6, 10))]
+    );
+    assert_eq!(
+        errors("a /* b * / c"),
+        [LexError::UnterminatedComment(Span::new(2, 12))]
+    );
+    assert_eq!(
+        errors("/* a */ \"b"),
+        [LexError::UnterminatedString(Span::new(8, 10))]
+    );
+    assert!(errors("/* a * b / c */ 1.5").is_empty());
+}
+
+#[test]
+fn test_string_escapes() {
+    let kinds = |src| Lexer::new(src).map(|token| token.kind).collect::<Vec<_>>();
+
+    assert_eq!(
+        kinds(r#""a\n\t\r\\\"\0b""#)[0],
+        String(Symbol::intern("a\n\t\r\\\"\0b"))
+    );
+    assert_eq!(
+        kinds(r#""\u{48}\u{e9}\u{1F600}""#)[0],
+        String(Symbol::intern("Hé😀"))
+    );
+    assert_eq!(
+        kinds(r#""a\qb\u{110000}" 1"#),
+        [
+            Error(LexError::InvalidEscape(Span::new(2, 4))),
+            Error(LexError::InvalidUnicodeEscape(Span::new(5, 15))),
+            Skip(' '),
+            Number(1.0),
+            Eof,
+        ]
+    );
+    assert_eq!(
+        kinds(r#""\u{}" "\"#)[..3],
+        [
+            Error(LexError::InvalidUnicodeEscape(Span::new(1, 5))),
+            Skip(' '),
+            Error(LexError::UnterminatedString(Span::new(7, 9))),
+        ]
+    );
+}
+
+#[test]
+fn test_interpolation_tokens() {
+    let kinds = Lexer::new(r#""a${b + "${c}"}{${{}}}" d"#)
+        .map(|token| token.kind)
+        .filter(|kind| !matches!(kind, Skip(_)))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        kinds,
+        [
+            InterpolationStart(Symbol::intern("a")),
+            Identifier(Symbol::intern("b")),
+            Plus,
+            InterpolationStart(Symbol::EMPTY),
+            Identifier(Symbol::intern("c")),
+            InterpolationEnd(Symbol::EMPTY),
+            InterpolationMiddle(Symbol::intern("{")),
+            LeftBrace,
+            RightBrace,
+            InterpolationEnd(Symbol::intern("}")),
+            Identifier(Symbol::intern("d")),
+            Eof,
+        ]
+    );
+}
+
+#[test]
+fn test_numbers() {
+    let kinds = |src| Lexer::new(src).map(|token| token.kind).collect::<Vec<_>>();
+
+    for (src, value) in [
+        ("0xFF", 255.0),
+        ("0Xff", 255.0),
+        ("0b1010", 10.0),
+        ("0o755", 493.0),
+        ("1e-9", 1e-9),
+        ("6.02E23", 6.02e23),
+        ("2.5e+3", 2500.0),
+        ("1_000_000", 1e6),
+        ("0xFF_FF", 65535.0),
+        ("1_0.2_5", 10.25),
+    ] {
+        assert_eq!(kinds(src), [Number(value), Eof], "{}", src);
+    }
+
+    for src in [
+        "0x", "1e", "1e+", "0b102", "1__0", "1_", "0o8", "0x_1", "12px",
+    ] {
+        let span = Span::new(0, src.len());
+        assert_eq!(
+            kinds(src),
+            [Error(LexError::MalformedNumber(span)), Eof],
+            "{}",
+            src
+        );
+    }
+
+    assert_eq!(kinds("1.foo")[..2], [Number(1.0), Dot]);
+}
+
+#[test]
+fn test_identifiers() {
+    let kinds = |src| Lexer::new(src).map(|token| token.kind).collect::<Vec<_>>();
+
+    for name in ["_private", "snake_case", "_", "x1", "café", "变量", "ñ_2"] {
+        assert_eq!(
+            kinds(name),
+            [Identifier(Symbol::intern(name)), Eof],
+            "{}",
+            name
+        );
+    }
+
+    assert_eq!(
+        kinds("9lives")[0],
+        Error(LexError::MalformedNumber(Span::new(0, 6)))
+    );
+    assert!(matches!(
+        kinds("€")[0],
+        Error(LexError::UnexpectedCharacter('€', _))
+    ));
+
+    #[cfg(feature = "nfc")]
+    assert_eq!(kinds("cafe\u{301}")[0], Identifier(Symbol::intern("café")));
+}
+
+#[test]
+fn test_confusable_identifiers() {
+    let confusables = confusable_identifiers(Lexer::new(
+        "var a = 1; print \u{430}; print \u{430} + a + b;",
+    ));
+
+    assert_eq!(confusables.len(), 1);
+    let (first, second) = confusables[0];
+    assert_eq!(first.to_string(), "a");
+    assert_eq!(second.to_string(), "\u{430}");
+    assert_eq!(second.span, Span::new(17, 19));
+}
@@ -1,16 +1,31 @@
-use std::{fmt, io::Write};
+use std::fmt;
 
 use crate::{
+    chunk::FunctionProto,
+    compiler::{CompileError, Compiler},
+    diagnostics::{Diagnostic, SourceFile, SourceMap},
+    disassembler,
     interpreter::{Interpreter, RuntimeError},
     lexer::*,
-    parser::{Parser, ParserError},
+    loxc::{self, LoadError},
+    optimizer::{self, OptLevel},
+    parser::{Parser, ParserError, MAX_ARGUMENTS},
+    repl::Repl,
+    resolver::{Resolver, ResolverError},
+    stmt::Stmt,
+    token::SourceId,
+    vm::Vm,
 };
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum LoxError {
     IoError(std::io::Error),
-    ParserError(String),
-    RuntimeError(String),
+    ParserError(Vec<Diagnostic>),
+    ResolverError(Vec<Diagnostic>),
+    CompileError(Vec<Diagnostic>),
+    BytecodeError(Diagnostic),
+    RuntimeError(Diagnostic),
 }
 
 impl std::error::Error for LoxError {}
@@ -21,26 +36,147 @@ impl From<std::io::Error> for LoxError {
     }
 }
 
-impl From<ParserError<'_>> for LoxError {
+impl From<ParserError> for Diagnostic {
     fn from(value: ParserError) -> Self {
         match value {
-            ParserError::Eof => Self::ParserError("Reached enf of file".to_string()),
-            ParserError::UnexpectedToken(e) => Self::ParserError(format!("Unexpected token {}", e)),
+            ParserError::LexError(e) => Diagnostic::new(e.to_string(), Some(e.span())),
+            ParserError::Eof => Diagnostic::new("Reached end of file".to_string(), None),
+            ParserError::UnexpectedToken(e) => {
+                Diagnostic::new(format!("Unexpected token '{}'", e), Some(e.span))
+            }
             ParserError::UnexpectedBinaryOp(e) => {
-                Self::ParserError(format!("Unexpected binary op {}", e))
+                Diagnostic::new(format!("Unexpected binary op '{}'", e), Some(e.span))
+            }
+            ParserError::InvalidAssignmentTarget(e) => {
+                Diagnostic::new("Invalid assignment target".to_string(), Some(e.span))
             }
+            ParserError::TooManyArguments(e) => Diagnostic::new(
+                format!("Can't have more than {} arguments", MAX_ARGUMENTS),
+                Some(e.span),
+            ),
         }
     }
 }
 
+impl From<Vec<ParserError>> for LoxError {
+    fn from(value: Vec<ParserError>) -> Self {
+        Self::ParserError(value.into_iter().map(Diagnostic::from).collect())
+    }
+}
+
+impl From<Vec<ResolverError>> for LoxError {
+    fn from(value: Vec<ResolverError>) -> Self {
+        let diagnostics = value
+            .into_iter()
+            .map(|error| match error {
+                ResolverError::ReadInOwnInitializer(e) => Diagnostic::new(
+                    format!("Can't read local variable '{}' in its own initializer", e),
+                    Some(e.span),
+                ),
+                ResolverError::AlreadyDeclared(e) => Diagnostic::new(
+                    format!("Already a variable named '{}' in this scope", e),
+                    Some(e.span),
+                ),
+                ResolverError::TopLevelReturn(e) => {
+                    Diagnostic::new("Can't return from top-level code".to_string(), Some(e.span))
+                }
+                ResolverError::ReturnFromInitializer(e) => Diagnostic::new(
+                    "Can't return a value from an initializer".to_string(),
+                    Some(e.span),
+                ),
+                ResolverError::ThisOutsideClass(e) => Diagnostic::new(
+                    "Can't use 'this' outside of a class".to_string(),
+                    Some(e.span),
+                ),
+                ResolverError::SuperOutsideClass(e) => Diagnostic::new(
+                    "Can't use 'super' outside of a class".to_string(),
+                    Some(e.span),
+                ),
+                ResolverError::SuperWithoutSuperclass(e) => Diagnostic::new(
+                    "Can't use 'super' in a class with no superclass".to_string(),
+                    Some(e.span),
+                ),
+                ResolverError::InheritsFromItself(e) => Diagnostic::new(
+                    "A class can't inherit from itself".to_string(),
+                    Some(e.span),
+                ),
+            })
+            .collect();
+
+        Self::ResolverError(diagnostics)
+    }
+}
+
+impl From<Vec<CompileError>> for LoxError {
+    fn from(value: Vec<CompileError>) -> Self {
+        let diagnostics = value
+            .into_iter()
+            .map(|error| match error {
+                CompileError::TooManyConstants(span) => {
+                    Diagnostic::new("Too many constants in one chunk".to_string(), Some(span))
+                }
+                CompileError::TooManyLocals(span) => Diagnostic::new(
+                    "Too many local variables in function".to_string(),
+                    Some(span),
+                ),
+                CompileError::TooManyUpvalues(span) => Diagnostic::new(
+                    "Too many closure variables in function".to_string(),
+                    Some(span),
+                ),
+                CompileError::JumpTooLarge(span) => {
+                    Diagnostic::new("Too much code to jump over".to_string(), Some(span))
+                }
+            })
+            .collect();
+
+        Self::CompileError(diagnostics)
+    }
+}
+
+impl From<LoadError> for LoxError {
+    fn from(value: LoadError) -> Self {
+        Self::BytecodeError(Diagnostic::new(value.to_string(), None))
+    }
+}
+
 impl From<RuntimeError> for LoxError {
     fn from(value: RuntimeError) -> Self {
-        match value {
-            RuntimeError::DivisionByZero => {
-                Self::RuntimeError("Runtime error division by zero".to_string())
-            }
-            RuntimeError::InvalidOperand => Self::RuntimeError("Invalid operand".to_string()),
-            RuntimeError::InvalidOperation => Self::RuntimeError("Invalid operation".to_string()),
+        Self::RuntimeError(Diagnostic::new(value.to_string(), Some(value.span())))
+    }
+}
+
+impl LoxError {
+    /// Exit status following the sysexits convention used by jlox.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::IoError(_) => 74,
+            Self::ParserError(_)
+            | Self::ResolverError(_)
+            | Self::CompileError(_)
+            | Self::BytecodeError(_) => 65,
+            Self::RuntimeError(_) => 70,
+        }
+    }
+
+    /// Resolves the spans of every diagnostic against `source`.
+    pub fn with_source(self, source: &SourceFile) -> Self {
+        self.map_diagnostics(|e| e.with_source(source))
+    }
+
+    /// Resolves the spans of every diagnostic against whichever source in
+    /// `sources` they point into.
+    pub fn with_sources(self, sources: &SourceMap) -> Self {
+        self.map_diagnostics(|e| e.with_sources(sources))
+    }
+
+    fn map_diagnostics(self, f: impl Fn(Diagnostic) -> Diagnostic) -> Self {
+        match self {
+            Self::IoError(e) => Self::IoError(e),
+            Self::ParserError(e) => Self::ParserError(e.into_iter().map(f).collect()),
+            Self::ResolverError(e) => Self::ResolverError(e.into_iter().map(f).collect()),
+            Self::CompileError(e) => Self::CompileError(e.into_iter().map(f).collect()),
+            Self::BytecodeError(e) => Self::BytecodeError(e),
+            Self::RuntimeError(e) => Self::RuntimeError(f(e)),
         }
     }
 }
@@ -49,56 +185,190 @@ impl fmt::Display for LoxError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Self::IoError(e) => write!(f, "IoError: {}", e),
-            Self::ParserError(e) => write!(f, "Parser Error: {}", e),
-            Self::RuntimeError(e) => write!(f, "Runtime Error: {}", e),
+            Self::ParserError(e) => write_all(f, e, "Parser Error"),
+            Self::ResolverError(e) => write_all(f, e, "Resolver Error"),
+            Self::CompileError(e) => write_all(f, e, "Compile Error"),
+            Self::BytecodeError(e) => write!(f, "{}", e.render("Bytecode Error")),
+            Self::RuntimeError(e) => write!(f, "{}", e.render("Runtime Error")),
+        }
+    }
+}
+
+fn write_all(f: &mut fmt::Formatter, diagnostics: &[Diagnostic], title: &str) -> fmt::Result {
+    for (i, diagnostic) in diagnostics.iter().enumerate() {
+        if i > 0 {
+            writeln!(f)?;
         }
+        write!(f, "{}", diagnostic.render(title))?;
     }
+    Ok(())
+}
+
+/// Which engine runs scripts.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Backend {
+    #[default]
+    TreeWalker,
+    Vm,
+}
+
+/// Garbage collector settings, for either backend.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GcOptions {
+    /// Collect before every allocation.
+    pub stress: bool,
+    /// Print collector counters to stderr when the script ends.
+    pub stats: bool,
 }
 
 #[derive(Debug, Default)]
 pub struct Lox {
     contents: String,
+    backend: Backend,
+    gc: GcOptions,
+    opt: OptLevel,
 }
 
 impl Lox {
-    pub fn new() -> Lox {
+    pub fn new(backend: Backend, gc: GcOptions, opt: OptLevel) -> Lox {
         Lox {
             contents: String::new(),
+            backend,
+            gc,
+            opt,
         }
     }
 
+    /// Runs a script, or a compiled `.loxc` file on the VM.
     pub fn run_file(&mut self, path: &str) -> Result<(), LoxError> {
-        self.contents = std::fs::read_to_string(path).unwrap();
+        if is_bytecode(path) {
+            let file = loxc::read(&std::fs::read(path)?)?;
+            return self.run_vm(file.function);
+        }
 
-        let lexer = Lexer::new(&self.contents);
+        self.contents = std::fs::read_to_string(path)?;
+        let file = SourceFile::new(path, &self.contents);
 
-        let mut parser = Parser::new(lexer);
+        let result = match self.backend {
+            Backend::TreeWalker => {
+                let mut interpreter = Interpreter::new();
+                interpreter.set_gc_stress(self.gc.stress);
 
-        let expr = parser.parse()?;
+                let result = run(&mut interpreter, file, SourceId::default(), self.opt);
+                if self.gc.stats {
+                    eprintln!("{}", interpreter.gc_stats());
+                }
+                result
+            }
+            Backend::Vm => compile(file, self.opt).and_then(|function| self.run_vm(function)),
+        };
 
-        let mut interpreter = Interpreter::new();
+        result.map_err(|e| e.with_source(&file))
+    }
 
-        println!("{:?}", interpreter.interpret(expr)?);
+    fn run_vm(&self, function: FunctionProto) -> Result<(), LoxError> {
+        let mut vm = Vm::new();
+        vm.set_gc_stress(self.gc.stress);
 
-        Ok(())
+        let result = vm.interpret(function.into());
+        if self.gc.stats {
+            eprintln!("{}", vm.gc_stats());
+        }
+
+        Ok(result?)
     }
 
-    pub fn run_prompt(&self) -> Result<(), LoxError> {
-        let mut line = String::new();
-
-        loop {
-            std::io::stdout().flush().unwrap();
-            print!(">");
-            std::io::stdin().read_line(&mut line).unwrap();
-            if line == "exit" {
-                break;
-            }
-            let lexer = Lexer::new(&line);
+    /// Compiles the script at `input` and writes the bytecode to `output`.
+    pub fn compile_file(&mut self, input: &str, output: &str) -> Result<(), LoxError> {
+        self.contents = std::fs::read_to_string(input)?;
+        let file = SourceFile::new(input, &self.contents);
 
-            let mut parser = Parser::new(lexer);
-            println!("{}", parser.parse()?);
+        let function = compile(file, self.opt).map_err(|e| e.with_source(&file))?;
+        std::fs::write(output, loxc::write(&function, &self.contents))?;
+
+        Ok(())
+    }
+
+    /// Prints the bytecode of a script or of a compiled `.loxc` file.
+    pub fn disassemble_file(&mut self, path: &str) -> Result<(), LoxError> {
+        if is_bytecode(path) {
+            let file = loxc::read(&std::fs::read(path)?)?;
+            println!("; source hash {:016x}", file.source_hash);
+            print!("{}", disassembler::disassemble(&file.function));
+            return Ok(());
         }
 
+        self.contents = std::fs::read_to_string(path)?;
+        let file = SourceFile::new(path, &self.contents);
+
+        let function = compile(file, self.opt).map_err(|e| e.with_source(&file))?;
+        print!("{}", disassembler::disassemble(&function));
+
         Ok(())
     }
+
+    /// Starts the REPL. It always uses the tree-walker.
+    pub fn run_prompt(&self) -> Result<(), LoxError> {
+        Repl::new().with_opt_level(self.opt).with_gc(self.gc).run()
+    }
+}
+
+/// Parses, resolves, optimizes and runs `file` as a program in
+/// `interpreter`. Spans in the resulting tree and errors point into `source`.
+pub fn run(
+    interpreter: &mut Interpreter,
+    file: SourceFile,
+    source: SourceId,
+    opt: OptLevel,
+) -> Result<(), LoxError> {
+    let mut statements = parse(file, source)?;
+
+    Resolver::new().resolve(&mut statements)?;
+
+    interpreter.execute(&optimizer::optimize(statements, opt))?;
+
+    Ok(())
+}
+
+/// Parses `file` as a program. Every entry point parses through here or
+/// calls `warn_confusables` itself.
+fn parse(file: SourceFile, source: SourceId) -> Result<Vec<Stmt>, LoxError> {
+    let mut parser = Parser::new(Lexer::with_source(file.text(), source));
+
+    let (statements, errors) = parser.parse_program();
+    warn_confusables(&parser, &file);
+    if !errors.is_empty() {
+        return Err(errors.into());
+    }
+
+    Ok(statements)
+}
+
+/// Prints a warning for each identifier `parser` has read that looks like a
+/// different one.
+pub fn warn_confusables(parser: &Parser, file: &SourceFile) {
+    for (first, second) in parser.confusable_identifiers() {
+        let warning = Diagnostic::new(
+            format!("Identifier '{}' looks like '{}'", second, first),
+            Some(second.span),
+        );
+        eprintln!("{}", warning.with_source(file).render("Warning"));
+    }
+}
+
+fn is_bytecode(path: &str) -> bool {
+    std::path::Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension == "loxc")
+}
+
+/// Parses, resolves, optimizes and compiles `file` to bytecode.
+fn compile(file: SourceFile, opt: OptLevel) -> Result<FunctionProto, LoxError> {
+    let mut statements = parse(file, SourceId::default())?;
+
+    Resolver::new().resolve(&mut statements)?;
+
+    let statements = optimizer::optimize(statements, opt);
+
+    Ok(Compiler::new(file.text()).compile(&statements)?)
 }
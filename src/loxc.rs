@@ -0,0 +1,548 @@
+use std::rc::Rc;
+
+use crate::{
+    chunk::{Chunk, Constant, FunctionProto, LineInfo, OpCode},
+    intern::Symbol,
+    token::Span,
+};
+
+/// Every `.loxc` file starts with these bytes.
+pub const MAGIC: &[u8; 4] = b"LOXC";
+
+/// Bumped whenever the layout or the instruction set changes. Files with a
+/// different version are rejected rather than guessed at.
+pub const FORMAT_VERSION: u16 = 2;
+
+const TAG_NUMBER: u8 = 0;
+const TAG_STRING: u8 = 1;
+const TAG_FUNCTION: u8 = 2;
+
+/// A compiled script as stored on disk.
+///
+/// ```text
+/// magic            b"LOXC"
+/// format version   u16
+/// source hash      u64, FNV-1a of the source text
+/// function         the top-level script, see `write_function`
+/// ```
+///
+/// All integers are little endian.
+#[derive(Debug)]
+pub struct CompiledFile {
+    pub source_hash: u64,
+    pub function: FunctionProto,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LoadError {
+    NotBytecode,
+    UnsupportedVersion(u16),
+    Truncated,
+    Malformed(&'static str),
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::NotBytecode => write!(f, "Not a compiled Lox file"),
+            LoadError::UnsupportedVersion(version) => write!(
+                f,
+                "Compiled with bytecode format version {}, but this jlox reads version {}; recompile it with `jlox compile`",
+                version, FORMAT_VERSION
+            ),
+            LoadError::Truncated => write!(f, "Compiled file is truncated"),
+            LoadError::Malformed(reason) => write!(f, "Compiled file is malformed: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// FNV-1a, chosen because it is stable across Rust versions and platforms.
+pub fn source_hash(source: &str) -> u64 {
+    source.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+pub fn write(function: &FunctionProto, source: &str) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    out.extend_from_slice(&source_hash(source).to_le_bytes());
+    write_function(&mut out, function);
+    out
+}
+
+/// ```text
+/// name             string
+/// arity            u32
+/// upvalue count    u32
+/// code             u32 length, then the bytes
+/// constants        u32 count, then a tag byte and payload for each
+/// lines            u32 count, then offset, line, span start and end as u32
+/// ```
+fn write_function(out: &mut Vec<u8>, function: &FunctionProto) {
+    write_string(out, &function.name);
+    write_u32(out, function.arity);
+    write_u32(out, function.upvalue_count);
+
+    let chunk = &function.chunk;
+    write_u32(out, chunk.code.len());
+    out.extend_from_slice(&chunk.code);
+
+    write_u32(out, chunk.constants.len());
+    for constant in &chunk.constants {
+        match constant {
+            Constant::Number(v) => {
+                out.push(TAG_NUMBER);
+                out.extend_from_slice(&v.to_le_bytes());
+            }
+            Constant::String(v) => {
+                out.push(TAG_STRING);
+                write_string(out, &v.as_str());
+            }
+            Constant::Function(v) => {
+                out.push(TAG_FUNCTION);
+                write_function(out, v);
+            }
+        }
+    }
+
+    write_u32(out, chunk.lines.len());
+    for info in &chunk.lines {
+        write_u32(out, info.offset);
+        write_u32(out, info.line);
+        write_u32(out, info.span.start());
+        write_u32(out, info.span.end());
+    }
+}
+
+fn write_u32(out: &mut Vec<u8>, value: usize) {
+    out.extend_from_slice(&(value as u32).to_le_bytes());
+}
+
+fn write_string(out: &mut Vec<u8>, value: &str) {
+    write_u32(out, value.len());
+    out.extend_from_slice(value.as_bytes());
+}
+
+pub fn read(bytes: &[u8]) -> Result<CompiledFile, LoadError> {
+    let mut reader = Reader { bytes, position: 0 };
+
+    if reader.take(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+        return Err(LoadError::NotBytecode);
+    }
+
+    let version = u16::from_le_bytes(reader.array()?);
+    if version != FORMAT_VERSION {
+        return Err(LoadError::UnsupportedVersion(version));
+    }
+
+    let source_hash = u64::from_le_bytes(reader.array()?);
+    let function = reader.function()?;
+
+    if reader.position != bytes.len() {
+        return Err(LoadError::Malformed("trailing bytes"));
+    }
+
+    Ok(CompiledFile {
+        source_hash,
+        function,
+    })
+}
+
+struct Reader<'b> {
+    bytes: &'b [u8],
+    position: usize,
+}
+
+impl<'b> Reader<'b> {
+    fn take(&mut self, len: usize) -> Result<&'b [u8], LoadError> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or(LoadError::Truncated)?;
+
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], LoadError> {
+        Ok(self.take(N)?.try_into().expect("slice has length N"))
+    }
+
+    fn u32(&mut self) -> Result<usize, LoadError> {
+        Ok(u32::from_le_bytes(self.array()?) as usize)
+    }
+
+    fn string(&mut self) -> Result<String, LoadError> {
+        let len = self.u32()?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| LoadError::Malformed("invalid UTF-8"))
+    }
+
+    fn function(&mut self) -> Result<FunctionProto, LoadError> {
+        let name = self.string()?;
+        let arity = self.u32()?;
+        let upvalue_count = self.u32()?;
+
+        let len = self.u32()?;
+        let code = self.take(len)?.to_vec();
+
+        let count = self.u32()?;
+        let mut constants = Vec::new();
+        for _ in 0..count {
+            let constant = match self.array::<1>()?[0] {
+                TAG_NUMBER => Constant::Number(f64::from_le_bytes(self.array()?)),
+                TAG_STRING => Constant::String(Symbol::intern(&self.string()?)),
+                TAG_FUNCTION => Constant::Function(Rc::new(self.function()?)),
+                _ => return Err(LoadError::Malformed("unknown constant tag")),
+            };
+            constants.push(constant);
+        }
+
+        let count = self.u32()?;
+        let mut lines = Vec::new();
+        for _ in 0..count {
+            let offset = self.u32()?;
+            let line = self.u32()?;
+            let span = Span::new(self.u32()?, self.u32()?);
+            lines.push(LineInfo { offset, line, span });
+        }
+
+        let function = FunctionProto {
+            name,
+            arity,
+            upvalue_count,
+            chunk: Chunk {
+                code,
+                constants,
+                lines,
+            },
+        };
+        verify(&function)?;
+        Ok(function)
+    }
+}
+
+/// Checks that instructions in `function` only refer to code, constants,
+/// upvalues and stack slots that exist: every opcode is known, operands fit
+/// in the code, constant operands have the right kind, jumps land on an
+/// instruction and every instruction has a source position. See
+/// `verify_stack` for the stack checks.
+fn verify(function: &FunctionProto) -> Result<(), LoadError> {
+    let chunk = &function.chunk;
+    let code = &chunk.code;
+
+    if chunk.lines.first().map(|info| info.offset) != Some(0) {
+        return Err(LoadError::Malformed("missing line info"));
+    }
+    if chunk.lines.windows(2).any(|w| w[0].offset >= w[1].offset) {
+        return Err(LoadError::Malformed("line info out of order"));
+    }
+
+    let u16_at = |offset: usize| -> Result<usize, LoadError> {
+        match code.get(offset..offset + 2) {
+            Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]]) as usize),
+            None => Err(LoadError::Malformed("operand past end of code")),
+        }
+    };
+    let name_at = |offset: usize| match chunk.constants.get(u16_at(offset)?) {
+        Some(Constant::String(_)) => Ok(()),
+        _ => Err(LoadError::Malformed("expected a name constant")),
+    };
+
+    let mut lengths = vec![0; code.len()];
+    let mut offset = 0;
+    while offset < code.len() {
+        let start = offset;
+        let op =
+            OpCode::try_from(code[offset]).map_err(|_| LoadError::Malformed("unknown opcode"))?;
+
+        offset = match op {
+            OpCode::Constant => match chunk.constants.get(u16_at(offset + 1)?) {
+                Some(Constant::Number(_) | Constant::String(_)) => offset + 3,
+                _ => return Err(LoadError::Malformed("invalid constant operand")),
+            },
+            OpCode::GetGlobal
+            | OpCode::DefineGlobal
+            | OpCode::SetGlobal
+            | OpCode::GetProperty
+            | OpCode::SetProperty
+            | OpCode::GetSuper
+            | OpCode::Class
+            | OpCode::Method => {
+                name_at(offset + 1)?;
+                offset + 3
+            }
+            OpCode::GetLocal | OpCode::SetLocal => {
+                u16_at(offset + 1)?;
+                offset + 3
+            }
+            OpCode::GetUpvalue | OpCode::SetUpvalue => {
+                if u16_at(offset + 1)? >= function.upvalue_count {
+                    return Err(LoadError::Malformed("upvalue index out of range"));
+                }
+                offset + 3
+            }
+            OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop => {
+                let jump = u16_at(offset + 1)?;
+                let target = if op == OpCode::Loop {
+                    (offset + 3).checked_sub(jump)
+                } else {
+                    Some(offset + 3 + jump)
+                };
+                if target.is_none_or(|target| target > code.len()) {
+                    return Err(LoadError::Malformed("jump out of range"));
+                }
+                offset + 3
+            }
+            OpCode::Call => offset + 2,
+            OpCode::Closure => {
+                let Some(Constant::Function(inner)) = chunk.constants.get(u16_at(offset + 1)?)
+                else {
+                    return Err(LoadError::Malformed("invalid closure operand"));
+                };
+
+                let mut offset = offset + 3;
+                for _ in 0..inner.upvalue_count {
+                    let is_local = code.get(offset) == Some(&1);
+                    if !is_local && u16_at(offset + 1)? >= function.upvalue_count {
+                        return Err(LoadError::Malformed("upvalue index out of range"));
+                    }
+                    offset += 3;
+                }
+                offset
+            }
+            _ => offset + 1,
+        };
+        lengths[start] = offset - start;
+    }
+
+    if offset != code.len() {
+        return Err(LoadError::Malformed("operand past end of code"));
+    }
+
+    verify_stack(function, &lengths)
+}
+
+/// Follows every path through `function` from its entry, where the stack
+/// holds the callee and its arguments, and works out how many values are on
+/// the stack before each instruction. Paths that meet must agree, no
+/// instruction may pop more than is there, local slots and captured locals
+/// must be below the current height and execution must end in a `Return`.
+///
+/// `lengths` holds the length of the instruction starting at each offset, or
+/// zero inside an instruction; `verify` has already checked every operand.
+fn verify_stack(function: &FunctionProto, lengths: &[usize]) -> Result<(), LoadError> {
+    let code = &function.chunk.code;
+    let u16_at = |offset: usize| u16::from_be_bytes([code[offset + 1], code[offset + 2]]) as usize;
+
+    let mut heights: Vec<Option<usize>> = vec![None; code.len()];
+    let mut pending = vec![(0, function.arity + 1)];
+
+    while let Some((offset, height)) = pending.pop() {
+        if lengths.get(offset).is_none_or(|&length| length == 0) {
+            return Err(LoadError::Malformed(
+                "control flow lands outside an instruction",
+            ));
+        }
+        match heights[offset] {
+            Some(known) if known == height => continue,
+            Some(_) => return Err(LoadError::Malformed("stack height differs between paths")),
+            None => heights[offset] = Some(height),
+        }
+
+        let op = OpCode::try_from(code[offset]).expect("verified opcode");
+        let (pops, pushes) = match op {
+            OpCode::Constant
+            | OpCode::Nil
+            | OpCode::True
+            | OpCode::False
+            | OpCode::GetGlobal
+            | OpCode::GetUpvalue
+            | OpCode::Class => (0, 1),
+            OpCode::GetLocal | OpCode::SetLocal => {
+                if u16_at(offset) >= height {
+                    return Err(LoadError::Malformed("local slot out of range"));
+                }
+                if op == OpCode::GetLocal {
+                    (0, 1)
+                } else {
+                    (1, 1)
+                }
+            }
+            OpCode::Pop | OpCode::DefineGlobal | OpCode::Print | OpCode::CloseUpvalue => (1, 0),
+            OpCode::SetGlobal
+            | OpCode::SetUpvalue
+            | OpCode::GetProperty
+            | OpCode::Not
+            | OpCode::Negate
+            | OpCode::Stringify
+            | OpCode::JumpIfFalse => (1, 1),
+            OpCode::SetProperty
+            | OpCode::GetSuper
+            | OpCode::Equal
+            | OpCode::NotEqual
+            | OpCode::Greater
+            | OpCode::GreaterEqual
+            | OpCode::Less
+            | OpCode::LessEqual
+            | OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide
+            | OpCode::Inherit
+            | OpCode::Method => (2, 1),
+            OpCode::Ternary => (3, 1),
+            OpCode::Call => (code[offset + 1] as usize + 1, 1),
+            OpCode::Closure => {
+                let Some(Constant::Function(inner)) = function.chunk.constants.get(u16_at(offset))
+                else {
+                    unreachable!("verified closure operand");
+                };
+                for capture in 0..inner.upvalue_count {
+                    let operand = offset + 3 + capture * 3;
+                    if code[operand] == 1 && u16_at(operand) >= height {
+                        return Err(LoadError::Malformed("captured local out of range"));
+                    }
+                }
+                (0, 1)
+            }
+            OpCode::Jump | OpCode::Loop => (0, 0),
+            OpCode::Return => (1, 0),
+        };
+
+        let Some(after) = height.checked_sub(pops) else {
+            return Err(LoadError::Malformed("stack underflow"));
+        };
+        let after = after + pushes;
+
+        let next = offset + lengths[offset];
+        match op {
+            OpCode::Return => {}
+            OpCode::Jump => pending.push((next + u16_at(offset), after)),
+            OpCode::Loop => pending.push((next - u16_at(offset), after)),
+            OpCode::JumpIfFalse => {
+                pending.push((next + u16_at(offset), after));
+                pending.push((next, after));
+            }
+            _ => pending.push((next, after)),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{compiler::Compiler, disassembler, lexer::Lexer, parser::Parser};
+
+    use super::*;
+
+    fn compile(src: &str) -> FunctionProto {
+        let (statements, errors) = Parser::new(Lexer::new(src)).parse_program();
+        assert!(errors.is_empty());
+        Compiler::new(src).compile(&statements).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let src =
+            "var a = 1.5; fun f(x) { return a + x; } class A { m() { return \"s\"; } } print f(2);";
+        let function = compile(src);
+
+        let file = read(&write(&function, src)).unwrap();
+
+        assert_eq!(file.source_hash, source_hash(src));
+        assert_eq!(
+            disassembler::disassemble(&file.function),
+            disassembler::disassemble(&function)
+        );
+        assert_eq!(file.function.chunk.lines, function.chunk.lines);
+    }
+
+    #[test]
+    fn test_rejects_bad_files() {
+        let src = "print 1;";
+        let bytes = write(&compile(src), src);
+
+        assert_eq!(read(b"print 1;").unwrap_err(), LoadError::NotBytecode);
+        assert_eq!(
+            read(&bytes[..bytes.len() - 1]).unwrap_err(),
+            LoadError::Truncated
+        );
+
+        let mut newer = bytes.clone();
+        newer[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            read(&newer).unwrap_err(),
+            LoadError::UnsupportedVersion(FORMAT_VERSION + 1)
+        );
+
+        let mut corrupt = bytes.clone();
+        let code_start = 4 + 2 + 8 + 4 + "script".len() + 4 + 4 + 4;
+        corrupt[code_start] = 0xff;
+        assert_eq!(
+            read(&corrupt).unwrap_err(),
+            LoadError::Malformed("unknown opcode")
+        );
+    }
+
+    #[test]
+    fn test_rejects_bad_stack_use() {
+        let script = |ops: &[(OpCode, Option<u16>)]| {
+            let mut function = FunctionProto {
+                name: "script".to_string(),
+                ..Default::default()
+            };
+            for &(op, operand) in ops {
+                function.chunk.write_op(op, 1, Span::new(0, 0));
+                if let Some(operand) = operand {
+                    function.chunk.write_u16(operand);
+                }
+            }
+            read(&write(&function, "")).map(|_| ())
+        };
+
+        assert_eq!(
+            script(&[(OpCode::Nil, None), (OpCode::Return, None)]),
+            Ok(())
+        );
+        assert_eq!(
+            script(&[
+                (OpCode::GetLocal, Some(0xffff)),
+                (OpCode::Pop, None),
+                (OpCode::Nil, None),
+                (OpCode::Return, None),
+            ]),
+            Err(LoadError::Malformed("local slot out of range"))
+        );
+        assert_eq!(
+            script(&[
+                (OpCode::Pop, None),
+                (OpCode::Pop, None),
+                (OpCode::Return, None)
+            ]),
+            Err(LoadError::Malformed("stack underflow"))
+        );
+        assert_eq!(
+            script(&[(OpCode::Nil, None)]),
+            Err(LoadError::Malformed(
+                "control flow lands outside an instruction"
+            ))
+        );
+        assert_eq!(
+            script(&[
+                (OpCode::True, None),
+                (OpCode::JumpIfFalse, Some(1)),
+                (OpCode::Nil, None),
+                (OpCode::Return, None),
+            ]),
+            Err(LoadError::Malformed("stack height differs between paths"))
+        );
+    }
+}
@@ -1,24 +1,116 @@
+mod chunk;
+mod class;
+mod collector;
+mod compiler;
+mod diagnostics;
+mod disassembler;
+mod environment;
 mod expr;
+mod function;
+mod intern;
 mod interpreter;
 mod lexer;
 mod lox;
+mod loxc;
+mod object;
+mod optimizer;
 mod parser;
+mod repl;
+mod resolver;
+mod stmt;
 mod token;
+mod visit;
+mod vm;
 
-use std::env::{self};
+use std::env;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut args = env::args();
-    let len = args.len();
+use lox::{Backend, GcOptions};
+use optimizer::OptLevel;
 
-    let mut lox = lox::Lox::new();
-    if len > 2 {
-        println!("Usage: jlox [script]");
-    } else if len == 2 {
-        lox.run_file(args.nth(1).unwrap().as_str())?;
-    } else {
-        lox.run_prompt()?;
+const USAGE: &str = "\
+Usage: jlox [--backend tree|vm] [-O0|-O1] [--gc-stress] [--gc-stats] [script]
+       jlox compile [-O0|-O1] <script> [-o <output>]
+       jlox disasm <script>";
+
+fn main() {
+    let mut args = env::args().skip(1).peekable();
+
+    let result = match args.peek().map(String::as_str) {
+        Some("disasm") => match (args.nth(1), args.next()) {
+            (Some(path), None) => lox::Lox::default().disassemble_file(&path),
+            _ => usage(),
+        },
+        Some("compile") => {
+            args.next();
+            compile(args)
+        }
+        _ => run(args),
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(e.exit_code());
+    }
+}
+
+fn run(mut args: impl Iterator<Item = String>) -> Result<(), lox::LoxError> {
+    let mut backend = Backend::TreeWalker;
+    let mut gc = GcOptions::default();
+    let mut opt = OptLevel::default();
+    let mut script = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--backend" => {
+                backend = match args.next().as_deref() {
+                    Some("tree") => Backend::TreeWalker,
+                    Some("vm") => Backend::Vm,
+                    _ => usage(),
+                }
+            }
+            "-O0" => opt = OptLevel::O0,
+            "-O1" => opt = OptLevel::O1,
+            "--gc-stress" => gc.stress = true,
+            "--gc-stats" => gc.stats = true,
+            _ if script.is_none() && !arg.starts_with("--") => script = Some(arg),
+            _ => usage(),
+        }
+    }
+
+    let mut lox = lox::Lox::new(backend, gc, opt);
+    match script {
+        Some(path) => lox.run_file(&path),
+        None => lox.run_prompt(),
+    }
+}
+
+fn compile(mut args: impl Iterator<Item = String>) -> Result<(), lox::LoxError> {
+    let mut input = None;
+    let mut output = None;
+    let mut opt = OptLevel::default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = Some(args.next().unwrap_or_else(|| usage())),
+            "-O0" => opt = OptLevel::O0,
+            "-O1" => opt = OptLevel::O1,
+            _ if input.is_none() && !arg.starts_with('-') => input = Some(arg),
+            _ => usage(),
+        }
     }
 
-    Ok(())
+    let input = input.unwrap_or_else(|| usage());
+    let output = output.unwrap_or_else(|| {
+        std::path::Path::new(&input)
+            .with_extension("loxc")
+            .to_string_lossy()
+            .into_owned()
+    });
+
+    lox::Lox::new(Backend::default(), GcOptions::default(), opt).compile_file(&input, &output)
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(64);
 }
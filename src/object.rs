@@ -0,0 +1,520 @@
+use std::{
+    collections::HashMap,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use crate::{chunk::FunctionProto, intern::Symbol, interpreter::ValueType};
+
+pub use repr::Value;
+
+/// A decoded `Value`, for matching on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueKind {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    Object(ObjRef),
+}
+
+#[cfg(not(feature = "nan-boxing"))]
+mod repr {
+    use super::{ObjRef, ValueKind};
+
+    /// A runtime value of the bytecode VM. Everything larger than a number
+    /// lives on the `Heap` and is referred to by handle.
+    ///
+    /// This is the safe form, used when the `nan-boxing` feature is off. It
+    /// is easier to inspect in a debugger but twice the size.
+    #[derive(Clone, Copy)]
+    pub struct Value(ValueKind);
+
+    impl Value {
+        pub const NIL: Value = Value(ValueKind::Nil);
+
+        pub fn boolean(value: bool) -> Self {
+            Value(ValueKind::Boolean(value))
+        }
+
+        pub fn number(value: f64) -> Self {
+            Value(ValueKind::Number(value))
+        }
+
+        pub fn object(reference: ObjRef) -> Self {
+            Value(ValueKind::Object(reference))
+        }
+
+        pub fn kind(self) -> ValueKind {
+            self.0
+        }
+    }
+}
+
+/// Numbers are stored as themselves. Every other value is a quiet NaN with
+/// the top payload bits set, which no arithmetic result can produce once
+/// NaNs are canonicalised:
+///
+/// ```text
+/// nil      0x7ffc_0000_0000_0001
+/// false    0x7ffc_0000_0000_0002
+/// true     0x7ffc_0000_0000_0003
+/// object   0xfffc_0000_xxxx_xxxx   heap index in the low 32 bits
+/// ```
+#[cfg(feature = "nan-boxing")]
+mod repr {
+    use super::{ObjRef, ValueKind};
+
+    const QNAN: u64 = 0x7ffc_0000_0000_0000;
+    const SIGN_BIT: u64 = 0x8000_0000_0000_0000;
+    const TAG_NIL: u64 = 1;
+    const TAG_FALSE: u64 = 2;
+    const TAG_TRUE: u64 = 3;
+
+    /// A runtime value of the bytecode VM, packed into 64 bits. Everything
+    /// larger than a number lives on the `Heap` and is referred to by handle.
+    #[derive(Clone, Copy)]
+    pub struct Value(u64);
+
+    impl Value {
+        pub const NIL: Value = Value(QNAN | TAG_NIL);
+        const FALSE: Value = Value(QNAN | TAG_FALSE);
+        const TRUE: Value = Value(QNAN | TAG_TRUE);
+
+        pub fn boolean(value: bool) -> Self {
+            if value {
+                Value::TRUE
+            } else {
+                Value::FALSE
+            }
+        }
+
+        pub fn number(value: f64) -> Self {
+            if value.is_nan() {
+                Value(f64::NAN.to_bits())
+            } else {
+                Value(value.to_bits())
+            }
+        }
+
+        pub fn object(reference: ObjRef) -> Self {
+            Value(SIGN_BIT | QNAN | reference.0 as u64)
+        }
+
+        pub fn kind(self) -> ValueKind {
+            if self.0 & QNAN != QNAN {
+                ValueKind::Number(f64::from_bits(self.0))
+            } else if self.0 & SIGN_BIT != 0 {
+                ValueKind::Object(ObjRef(self.0 as u32))
+            } else {
+                match self.0 {
+                    v if v == Value::NIL.0 => ValueKind::Nil,
+                    v if v == Value::FALSE.0 => ValueKind::Boolean(false),
+                    v if v == Value::TRUE.0 => ValueKind::Boolean(true),
+                    v => unreachable!("invalid value bits {:#x}", v),
+                }
+            }
+        }
+    }
+}
+
+impl Value {
+    pub fn is_truthy(self) -> bool {
+        !matches!(self.kind(), ValueKind::Nil | ValueKind::Boolean(false))
+    }
+
+    pub fn as_number(self) -> Option<f64> {
+        match self.kind() {
+            ValueKind::Number(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(self) -> Option<ObjRef> {
+        match self.kind() {
+            ValueKind::Object(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+/// Numbers compare as `f64`, so `NaN != NaN` in both representations.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind() == other.kind()
+    }
+}
+
+impl std::fmt::Debug for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.kind().fmt(f)
+    }
+}
+
+/// Handle to an object on the `Heap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjRef(u32);
+
+#[derive(Debug)]
+pub enum Object {
+    String(Rc<str>),
+    Native(Native),
+    Closure(Closure),
+    Upvalue(Upvalue),
+    Class(Class),
+    Instance(Instance),
+    BoundMethod(BoundMethod),
+}
+
+#[derive(Debug)]
+pub struct Native {
+    pub arity: usize,
+    pub function: fn(&[Value]) -> Value,
+}
+
+#[derive(Debug)]
+pub struct Closure {
+    pub function: Rc<FunctionProto>,
+    pub upvalues: Vec<ObjRef>,
+}
+
+/// A captured variable. It points into the VM stack while the variable is
+/// in scope and holds the value itself once the scope has ended.
+#[derive(Debug)]
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+#[derive(Debug)]
+pub struct Class {
+    pub name: Symbol,
+    pub methods: HashMap<Symbol, ObjRef>,
+}
+
+#[derive(Debug)]
+pub struct Instance {
+    pub class: ObjRef,
+    pub fields: HashMap<Symbol, Value>,
+}
+
+#[derive(Debug)]
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: ObjRef,
+}
+
+impl Object {
+    /// Approximate number of bytes owned by the object, used to decide when
+    /// to collect.
+    fn size(&self) -> usize {
+        let payload = match self {
+            Object::String(v) => v.len(),
+            Object::Native(_) | Object::Upvalue(_) | Object::BoundMethod(_) => 0,
+            Object::Closure(v) => v.upvalues.capacity() * size_of::<ObjRef>(),
+            Object::Class(v) => v.methods.capacity() * size_of::<(Symbol, ObjRef)>(),
+            Object::Instance(v) => v.fields.capacity() * size_of::<(Symbol, Value)>(),
+        };
+        size_of::<Object>() + payload
+    }
+
+    /// Calls `visit` with every value the object keeps alive.
+    fn trace(&self, mut visit: impl FnMut(Value)) {
+        match self {
+            Object::String(_) | Object::Native(_) | Object::Upvalue(Upvalue::Open(_)) => {}
+            Object::Upvalue(Upvalue::Closed(value)) => visit(*value),
+            Object::Closure(v) => v.upvalues.iter().for_each(|&u| visit(Value::object(u))),
+            Object::Class(v) => v.methods.values().for_each(|&m| visit(Value::object(m))),
+            Object::Instance(v) => {
+                visit(Value::object(v.class));
+                v.fields.values().for_each(|&field| visit(field));
+            }
+            Object::BoundMethod(v) => {
+                visit(v.receiver);
+                visit(Value::object(v.method));
+            }
+        }
+    }
+}
+
+/// Collector counters, as returned by `Vm::gc_stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GcStats {
+    /// Total bytes allocated since the heap was created.
+    pub bytes_allocated: usize,
+    /// Bytes held by objects that survived the last collection, plus
+    /// everything allocated since.
+    pub bytes_live: usize,
+    pub collections: usize,
+    pub objects_freed: usize,
+    pub total_pause: Duration,
+    pub max_pause: Duration,
+}
+
+impl std::fmt::Display for GcStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "bytes allocated: {}\nbytes live: {}\ncollections: {}\nobjects freed: {}\ntotal pause: {:?}\nmax pause: {:?}",
+            self.bytes_allocated,
+            self.bytes_live,
+            self.collections,
+            self.objects_freed,
+            self.total_pause,
+            self.max_pause
+        )
+    }
+}
+
+/// Heap size at which the first collection runs.
+pub const INITIAL_THRESHOLD: usize = 1024 * 1024;
+
+/// After a collection the next one runs once the heap has grown by this
+/// factor over what survived.
+pub const GROWTH_FACTOR: usize = 2;
+
+/// Owner of every object created by the VM. Objects are reclaimed by a
+/// mark-and-sweep collector; the VM supplies the roots. The tree-walker
+/// shares its objects through `Rc` instead and frees cycles with
+/// `collector::Collector`.
+#[derive(Debug)]
+pub struct Heap {
+    objects: Vec<Option<Object>>,
+    marks: Vec<bool>,
+    free: Vec<u32>,
+    next_gc: usize,
+    /// Collect before every allocation, to flush out missing roots.
+    stress: bool,
+    stats: GcStats,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Heap {
+            objects: Vec::new(),
+            marks: Vec::new(),
+            free: Vec::new(),
+            next_gc: INITIAL_THRESHOLD,
+            stress: false,
+            stats: GcStats::default(),
+        }
+    }
+
+    pub fn set_stress(&mut self, stress: bool) {
+        self.stress = stress;
+    }
+
+    pub fn stats(&self) -> GcStats {
+        self.stats
+    }
+
+    /// Whether the owner should run `collect` before the next allocation.
+    pub fn should_collect(&self) -> bool {
+        self.stress || self.stats.bytes_live > self.next_gc
+    }
+
+    pub fn alloc(&mut self, object: Object) -> ObjRef {
+        let size = object.size();
+        self.stats.bytes_allocated += size;
+        self.stats.bytes_live += size;
+
+        match self.free.pop() {
+            Some(index) => {
+                self.objects[index as usize] = Some(object);
+                ObjRef(index)
+            }
+            None => {
+                self.objects.push(Some(object));
+                self.marks.push(false);
+                ObjRef(self.objects.len() as u32 - 1)
+            }
+        }
+    }
+
+    /// Frees every object not reachable from `roots`.
+    pub fn collect(&mut self, roots: impl IntoIterator<Item = Value>) {
+        let start = Instant::now();
+
+        let mut gray: Vec<ObjRef> = Vec::new();
+        let mark = |value: Value, marks: &mut Vec<bool>, gray: &mut Vec<ObjRef>| {
+            if let Some(reference) = value.as_object() {
+                let marked = &mut marks[reference.0 as usize];
+                if !*marked {
+                    *marked = true;
+                    gray.push(reference);
+                }
+            }
+        };
+
+        for root in roots {
+            mark(root, &mut self.marks, &mut gray);
+        }
+        while let Some(reference) = gray.pop() {
+            let object = self.objects[reference.0 as usize]
+                .as_ref()
+                .expect("marked object is live");
+            object.trace(|value| mark(value, &mut self.marks, &mut gray));
+        }
+
+        let mut live = 0;
+        for (index, (slot, marked)) in self.objects.iter_mut().zip(&mut self.marks).enumerate() {
+            match slot {
+                Some(object) if *marked => live += object.size(),
+                Some(_) => {
+                    *slot = None;
+                    self.free.push(index as u32);
+                    self.stats.objects_freed += 1;
+                }
+                None => {}
+            }
+            *marked = false;
+        }
+
+        self.stats.bytes_live = live;
+        self.next_gc = (live * GROWTH_FACTOR).max(INITIAL_THRESHOLD);
+
+        let pause = start.elapsed();
+        self.stats.collections += 1;
+        self.stats.total_pause += pause;
+        self.stats.max_pause = self.stats.max_pause.max(pause);
+    }
+
+    pub fn get(&self, reference: ObjRef) -> &Object {
+        self.objects[reference.0 as usize]
+            .as_ref()
+            .expect("reference to a collected object")
+    }
+
+    pub fn get_mut(&mut self, reference: ObjRef) -> &mut Object {
+        self.objects[reference.0 as usize]
+            .as_mut()
+            .expect("reference to a collected object")
+    }
+
+    pub fn closure(&self, reference: ObjRef) -> &Closure {
+        match self.get(reference) {
+            Object::Closure(v) => v,
+            other => unreachable!("expected a closure, found {:?}", other),
+        }
+    }
+
+    pub fn class(&self, reference: ObjRef) -> &Class {
+        match self.get(reference) {
+            Object::Class(v) => v,
+            other => unreachable!("expected a class, found {:?}", other),
+        }
+    }
+
+    pub fn class_mut(&mut self, reference: ObjRef) -> &mut Class {
+        match self.get_mut(reference) {
+            Object::Class(v) => v,
+            other => unreachable!("expected a class, found {:?}", other),
+        }
+    }
+
+    pub fn instance(&self, reference: ObjRef) -> &Instance {
+        match self.get(reference) {
+            Object::Instance(v) => v,
+            other => unreachable!("expected an instance, found {:?}", other),
+        }
+    }
+
+    pub fn instance_mut(&mut self, reference: ObjRef) -> &mut Instance {
+        match self.get_mut(reference) {
+            Object::Instance(v) => v,
+            other => unreachable!("expected an instance, found {:?}", other),
+        }
+    }
+
+    pub fn type_of(&self, value: Value) -> ValueType {
+        match value.kind() {
+            ValueKind::Nil => ValueType::Nil,
+            ValueKind::Boolean(_) => ValueType::Boolean,
+            ValueKind::Number(_) => ValueType::Number,
+            ValueKind::Object(reference) => match self.get(reference) {
+                Object::String(_) => ValueType::String,
+                Object::Native(_) | Object::Closure(_) | Object::BoundMethod(_) => {
+                    ValueType::Function
+                }
+                Object::Class(_) => ValueType::Class,
+                Object::Instance(_) => ValueType::Instance,
+                Object::Upvalue(_) => unreachable!("upvalues are never values"),
+            },
+        }
+    }
+
+    /// Lox equality: strings compare by contents, other objects by identity.
+    /// Strings loaded from the same constant share their text, so most
+    /// comparisons stop at the pointer check.
+    pub fn values_equal(&self, left: Value, right: Value) -> bool {
+        match (left.kind(), right.kind()) {
+            (ValueKind::Object(l), ValueKind::Object(r)) => match (self.get(l), self.get(r)) {
+                (Object::String(l), Object::String(r)) => Rc::ptr_eq(l, r) || l == r,
+                _ => l == r,
+            },
+            _ => left == right,
+        }
+    }
+
+    /// Formats `value` the same way the tree-walker prints an `Output`.
+    pub fn display(&self, value: Value) -> String {
+        match value.kind() {
+            ValueKind::Nil => "nil".to_string(),
+            ValueKind::Boolean(v) => v.to_string(),
+            ValueKind::Number(v) => v.to_string(),
+            ValueKind::Object(reference) => match self.get(reference) {
+                Object::String(v) => v.to_string(),
+                Object::Native(_) => "<native fn>".to_string(),
+                Object::Closure(v) => format!("<fn {}>", v.function.name),
+                Object::BoundMethod(v) => {
+                    format!("<fn {}>", self.closure(v.method).function.name)
+                }
+                Object::Class(v) => v.name.to_string(),
+                Object::Instance(v) => format!("<{} instance>", self.class(v.class).name),
+                Object::Upvalue(_) => unreachable!("upvalues are never values"),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_round_trip() {
+        let kinds = [
+            ValueKind::Nil,
+            ValueKind::Boolean(false),
+            ValueKind::Boolean(true),
+            ValueKind::Number(0.0),
+            ValueKind::Number(-1.5),
+            ValueKind::Number(f64::INFINITY),
+            ValueKind::Object(ObjRef(0)),
+            ValueKind::Object(ObjRef(u32::MAX)),
+        ];
+        for kind in kinds {
+            let value = match kind {
+                ValueKind::Nil => Value::NIL,
+                ValueKind::Boolean(v) => Value::boolean(v),
+                ValueKind::Number(v) => Value::number(v),
+                ValueKind::Object(v) => Value::object(v),
+            };
+            assert_eq!(value.kind(), kind);
+        }
+
+        let nan = Value::number(f64::NAN);
+        assert!(nan.as_number().is_some_and(f64::is_nan));
+        assert_ne!(nan, nan);
+        assert_eq!(Value::number(0.0), Value::number(-0.0));
+        assert!(!Value::NIL.is_truthy() && Value::number(0.0).is_truthy());
+
+        #[cfg(feature = "nan-boxing")]
+        assert_eq!(size_of::<Value>(), 8);
+    }
+}
@@ -0,0 +1,209 @@
+use crate::{
+    expr::*,
+    intern::Symbol,
+    stmt::Stmt,
+    token::TokenKind,
+    visit::{self, Fold},
+};
+
+/// How much the tree is rewritten after resolving, selected with `-O0` and
+/// `-O1`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum OptLevel {
+    /// Run the tree as parsed.
+    #[default]
+    O0,
+    /// Fold constant expressions and drop groupings.
+    O1,
+}
+
+/// Rewrites `statements` for `level`. The result behaves exactly like the
+/// input, including which runtime errors it raises.
+pub fn optimize(statements: Vec<Stmt>, level: OptLevel) -> Vec<Stmt> {
+    match level {
+        OptLevel::O0 => statements,
+        OptLevel::O1 => visit::fold_stmts(&mut ConstantFolder, statements),
+    }
+}
+
+/// Evaluates operators whose operands are all literals at compile time.
+///
+/// Anything that would fail at runtime, such as dividing by zero or adding
+/// a number to a string, is left in the tree so it still fails there.
+struct ConstantFolder;
+
+impl Fold for ConstantFolder {
+    fn fold_grouping(&mut self, expr: Grouping) -> Expr {
+        // Parentheses only matter to the parser.
+        self.fold_expr(*expr.expr)
+    }
+
+    fn fold_unary(&mut self, expr: Unary) -> Expr {
+        let right = self.fold_expr(*expr.right);
+
+        let value = match (expr.operator.kind, &right) {
+            (TokenKind::Minus, Expr::Literal(v)) => match v.value {
+                LiteralValue::Number(n) => Some(LiteralValue::Number(-n)),
+                _ => None,
+            },
+            (TokenKind::Bang, Expr::Literal(v)) => {
+                Some(LiteralValue::Boolean(!is_truthy(&v.value)))
+            }
+            _ => None,
+        };
+
+        constant(
+            Expr::Unary(Unary {
+                right: Box::new(right),
+                ..expr
+            }),
+            value,
+        )
+    }
+
+    fn fold_binary(&mut self, expr: Binary) -> Expr {
+        let left = self.fold_expr(*expr.left);
+        let right = self.fold_expr(*expr.right);
+
+        let value = match (&left, &right) {
+            (Expr::Literal(l), Expr::Literal(r)) => binary(expr.operator.kind, &l.value, &r.value),
+            _ => None,
+        };
+
+        constant(
+            Expr::Binary(Binary {
+                left: Box::new(left),
+                right: Box::new(right),
+                ..expr
+            }),
+            value,
+        )
+    }
+
+    fn fold_ternary(&mut self, expr: Ternary) -> Expr {
+        let left = self.fold_expr(*expr.left);
+        let middle = self.fold_expr(*expr.middle);
+        let right = self.fold_expr(*expr.right);
+
+        // Every branch is evaluated, so only fold when none of them can have
+        // side effects. A condition that isn't a boolean is a runtime error.
+        let value = match (&left, &middle, &right) {
+            (Expr::Literal(l), Expr::Literal(m), Expr::Literal(r)) => match l.value {
+                LiteralValue::Boolean(true) => Some(m.value.clone()),
+                LiteralValue::Boolean(false) => Some(r.value.clone()),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        constant(
+            Expr::Ternary(Ternary {
+                left: Box::new(left),
+                middle: Box::new(middle),
+                right: Box::new(right),
+                ..expr
+            }),
+            value,
+        )
+    }
+}
+
+/// Replaces `expr` with a literal covering the same source when it could be
+/// evaluated.
+fn constant(expr: Expr, value: Option<LiteralValue>) -> Expr {
+    match value {
+        Some(value) => Expr::Literal(Literal {
+            value,
+            span: expr.span(),
+        }),
+        None => expr,
+    }
+}
+
+fn binary(operator: TokenKind, left: &LiteralValue, right: &LiteralValue) -> Option<LiteralValue> {
+    use LiteralValue::*;
+
+    let value = match (operator, left, right) {
+        (TokenKind::Comma, _, r) => r.clone(),
+        (TokenKind::Plus, Number(l), Number(r)) => Number(l + r),
+        (TokenKind::Plus, String(l), String(r)) => {
+            String(Symbol::intern(&format!("{}{}", l.as_str(), r.as_str())))
+        }
+        (TokenKind::Minus, Number(l), Number(r)) => Number(l - r),
+        (TokenKind::Star, Number(l), Number(r)) => Number(l * r),
+        (TokenKind::Slash, Number(l), Number(r)) if *r != 0.0 => Number(l / r),
+        (TokenKind::Greater, Number(l), Number(r)) => Boolean(l > r),
+        (TokenKind::GreaterEqual, Number(l), Number(r)) => Boolean(l >= r),
+        (TokenKind::Less, Number(l), Number(r)) => Boolean(l < r),
+        (TokenKind::LessEqual, Number(l), Number(r)) => Boolean(l <= r),
+        (TokenKind::EqualEqual, l, r) => Boolean(equal(l, r)),
+        (TokenKind::BangEqual, l, r) => Boolean(!equal(l, r)),
+        _ => return None,
+    };
+
+    Some(value)
+}
+
+fn is_truthy(value: &LiteralValue) -> bool {
+    !matches!(value, LiteralValue::Nil | LiteralValue::Boolean(false))
+}
+
+fn equal(left: &LiteralValue, right: &LiteralValue) -> bool {
+    match (left, right) {
+        (LiteralValue::Number(l), LiteralValue::Number(r)) => l == r,
+        (LiteralValue::String(l), LiteralValue::String(r)) => l == r,
+        (LiteralValue::Boolean(l), LiteralValue::Boolean(r)) => l == r,
+        (LiteralValue::Nil, LiteralValue::Nil) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interpreter::{Interpreter, RuntimeError},
+        lexer::Lexer,
+        parser::Parser,
+        resolver::Resolver,
+    };
+
+    use super::*;
+
+    fn fold(src: &str) -> String {
+        let (statements, errors) = Parser::new(Lexer::new(src)).parse_program();
+        assert!(errors.is_empty());
+
+        optimize(statements, OptLevel::O1)
+            .iter()
+            .map(|statement| statement.to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn test_folds_constants() {
+        assert_eq!(fold("print (1 + 2) * 3;"), "(print 9);");
+        assert_eq!(fold("print \"a\" + \"b\";"), "(print ab);");
+        assert_eq!(fold("print !(1 < 2) == false;"), "(print true);");
+        assert_eq!(fold("print -(2 - 5);"), "(print 3);");
+        assert_eq!(fold("print 1 > 2 ? \"x\" : nil;"), "(print nil);");
+        assert_eq!(fold("print (a) + (1 + 1);"), "(print (+ a 2));");
+    }
+
+    #[test]
+    fn test_keeps_runtime_errors() {
+        assert_eq!(fold("print 4 / (1 - 1);"), "(print (/ 4 0));");
+        assert_eq!(fold("print 1 + \"a\";"), "(print (+ 1 a));");
+        assert_eq!(fold("print -nil;"), "(print (- nil));");
+        assert_eq!(fold("print 1 ? 2 : 3;"), "(print (1 ? 2 : 3));");
+        assert_eq!(
+            fold("print true ? 1 : f();"),
+            "(print (true ? 1 : (call f)));"
+        );
+
+        let (mut statements, _) = Parser::new(Lexer::new("print 4 / (1 - 1);")).parse_program();
+        Resolver::new().resolve(&mut statements).unwrap();
+        let result = Interpreter::new().execute(&optimize(statements, OptLevel::O1));
+        assert!(matches!(result, Err(RuntimeError::DivisionByZero(_))));
+    }
+}
@@ -1,5 +1,10 @@
+use std::rc::Rc;
+
 use crate::{
-    expr::*,
+    expr::{self, *},
+    intern::Symbol,
+    lexer::{self, LexError},
+    stmt::{self, Stmt},
     token::{
         Token,
         TokenKind::{self, *},
@@ -7,33 +12,326 @@ use crate::{
 };
 
 #[derive(Debug)]
-pub enum ParserError<'a> {
+pub enum ParserError {
+    LexError(LexError),
     Eof,
-    UnexpectedToken(Token<'a>),
-    UnexpectedBinaryOp(Token<'a>),
+    UnexpectedToken(Token),
+    UnexpectedBinaryOp(Token),
+    InvalidAssignmentTarget(Token),
+    TooManyArguments(Token),
 }
 
+/// Calls and function declarations are limited to this many arguments so the
+/// count fits in a single byte.
+pub const MAX_ARGUMENTS: usize = 255;
+
 #[derive(Debug)]
-pub struct Parser<'a> {
-    tokens: Vec<Token<'a>>,
+pub struct Parser {
+    tokens: Vec<Token>,
     current: usize,
+    errors: Vec<ParserError>,
+    block_depth: usize,
 }
 
-impl<'a> Parser<'a> {
-    pub fn new(tokens: impl Iterator<Item = Token<'a>>) -> Parser<'a> {
+impl Parser {
+    /// Collects the tokens to parse. Lexical errors stay in the stream so
+    /// that they are reported in source order along with the syntax errors.
+    pub fn new(tokens: impl Iterator<Item = Token>) -> Parser {
+        let tokens = tokens
+            .filter(|t| !matches!(t.kind, TokenKind::Skip(_) | TokenKind::Comment))
+            .collect();
+
         Parser {
-            tokens: tokens
-                .filter(|t| !(matches!(t.kind, TokenKind::Skip(_))))
-                .collect(),
+            tokens,
             current: 0,
+            errors: Vec::new(),
+            block_depth: 0,
+        }
+    }
+
+    /// Identifiers in the input that look like a different one, paired with
+    /// the identifier they look like. See `lexer::confusable_identifiers`.
+    pub fn confusable_identifiers(&self) -> Vec<(Token, Token)> {
+        lexer::confusable_identifiers(self.tokens.iter().copied())
+    }
+
+    /// Parses a whole program, recovering at statement boundaries so that
+    /// every syntax error is reported. Returns the statements that parsed
+    /// successfully along with the errors; the program is only valid when
+    /// the error list is empty.
+    pub fn parse_program(&mut self) -> (Vec<Stmt>, Vec<ParserError>) {
+        let mut statements = Vec::new();
+
+        while !self.is_at_end() {
+            if let Some(statement) = self.declaration() {
+                statements.push(statement);
+            }
+        }
+
+        (statements, std::mem::take(&mut self.errors))
+    }
+
+    fn declaration(&mut self) -> Option<Stmt> {
+        let start = self.current;
+
+        match self.try_declaration() {
+            Ok(statement) => Some(statement),
+            Err(e) => {
+                // `advance` records lexical errors as it passes their token.
+                if !matches!(e, ParserError::LexError(_)) {
+                    self.errors.push(e);
+                }
+                self.synchronize();
+
+                if self.current == start {
+                    self.advance();
+                }
+                None
+            }
+        }
+    }
+
+    fn try_declaration(&mut self) -> Result<Stmt, ParserError> {
+        match self.peek(0).kind {
+            Var => {
+                self.advance();
+                self.var_declaration()
+            }
+            Fun => {
+                self.advance();
+                Ok(Stmt::Function(self.function()?))
+            }
+            Class => {
+                self.advance();
+                self.class_declaration()
+            }
+            _ => self.statement(),
+        }
+    }
+
+    fn class_declaration(&mut self) -> Result<Stmt, ParserError> {
+        let name = self.consume_identifier()?;
+
+        let superclass = if let Less = self.peek(0).kind {
+            self.advance();
+            Some(Variable::new(self.consume_identifier()?))
+        } else {
+            None
+        };
+
+        self.consume(LeftBrace)?;
+
+        let mut methods = Vec::new();
+        while !matches!(self.peek(0).kind, RightBrace | Eof) {
+            methods.push(self.function()?);
+        }
+
+        self.consume(RightBrace)?;
+        Ok(Stmt::Class(stmt::Class::new(name, superclass, methods)))
+    }
+
+    fn function(&mut self) -> Result<Rc<stmt::Function>, ParserError> {
+        let name = self.consume_identifier()?;
+        self.consume(LeftParen)?;
+
+        let mut params = Vec::new();
+        if self.peek(0).kind != RightParen {
+            loop {
+                if params.len() >= MAX_ARGUMENTS {
+                    return Err(ParserError::TooManyArguments(self.peek(0)));
+                }
+                params.push(self.consume_identifier()?);
+
+                if self.peek(0).kind != Comma {
+                    break;
+                }
+                self.advance();
+            }
+        }
+        self.consume(RightParen)?;
+
+        self.consume(LeftBrace)?;
+        let body = self.block()?;
+
+        Ok(Rc::new(stmt::Function::new(name, params, body)))
+    }
+
+    fn var_declaration(&mut self) -> Result<Stmt, ParserError> {
+        let name = self.consume_identifier()?;
+
+        let initializer = if let Equal = self.peek(0).kind {
+            self.advance();
+            Some(self.comma()?)
+        } else {
+            None
+        };
+
+        self.consume(SemiColon)?;
+        Ok(Stmt::Var(stmt::Var::new(name, initializer)))
+    }
+
+    fn statement(&mut self) -> Result<Stmt, ParserError> {
+        match self.peek(0).kind {
+            Print => {
+                self.advance();
+                self.print_statement()
+            }
+            LeftBrace => {
+                self.advance();
+                Ok(Stmt::Block(stmt::Block::new(self.block()?)))
+            }
+            If => {
+                self.advance();
+                self.if_statement()
+            }
+            While => {
+                self.advance();
+                self.while_statement()
+            }
+            For => {
+                self.advance();
+                self.for_statement()
+            }
+            Return => {
+                let keyword = self.advance();
+                self.return_statement(keyword)
+            }
+            _ => self.expression_statement(),
         }
     }
 
-    pub fn parse(&mut self) -> Result<Expr<'a>, ParserError<'a>> {
-        self.comma()
+    fn return_statement(&mut self, keyword: Token) -> Result<Stmt, ParserError> {
+        let value = match self.peek(0).kind {
+            SemiColon => None,
+            _ => Some(self.comma()?),
+        };
+        self.consume(SemiColon)?;
+
+        Ok(Stmt::Return(stmt::Return::new(keyword, value)))
+    }
+
+    fn if_statement(&mut self) -> Result<Stmt, ParserError> {
+        self.consume(LeftParen)?;
+        let condition = self.comma()?;
+        self.consume(RightParen)?;
+
+        let then_branch = self.statement()?;
+        let else_branch = if let Else = self.peek(0).kind {
+            self.advance();
+            Some(self.statement()?)
+        } else {
+            None
+        };
+
+        Ok(Stmt::If(stmt::If::new(condition, then_branch, else_branch)))
+    }
+
+    fn while_statement(&mut self) -> Result<Stmt, ParserError> {
+        self.consume(LeftParen)?;
+        let condition = self.comma()?;
+        self.consume(RightParen)?;
+        let body = self.statement()?;
+
+        Ok(Stmt::While(stmt::While::new(condition, body)))
+    }
+
+    /// Desugars `for (init; condition; increment) body` into
+    /// `{ init; while (condition) { body; increment; } }`.
+    fn for_statement(&mut self) -> Result<Stmt, ParserError> {
+        self.consume(LeftParen)?;
+
+        let initializer = match self.peek(0).kind {
+            SemiColon => {
+                self.advance();
+                None
+            }
+            Var => {
+                self.advance();
+                Some(self.var_declaration()?)
+            }
+            _ => Some(self.expression_statement()?),
+        };
+
+        let condition = match self.peek(0).kind {
+            SemiColon => None,
+            _ => Some(self.comma()?),
+        };
+        let semicolon = self.consume(SemiColon)?;
+
+        let increment = match self.peek(0).kind {
+            RightParen => None,
+            _ => Some(self.comma()?),
+        };
+        self.consume(RightParen)?;
+
+        let mut body = self.statement()?;
+
+        if let Some(increment) = increment {
+            body = Stmt::Block(stmt::Block::new(vec![
+                body,
+                Stmt::Expression(stmt::Expression::new(increment)),
+            ]));
+        }
+
+        let condition = condition.unwrap_or(Expr::Literal(Literal {
+            value: LiteralValue::Boolean(true),
+            span: semicolon.span,
+        }));
+        body = Stmt::While(stmt::While::new(condition, body));
+
+        if let Some(initializer) = initializer {
+            body = Stmt::Block(stmt::Block::new(vec![initializer, body]));
+        }
+
+        Ok(body)
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, ParserError> {
+        let mut statements = Vec::new();
+
+        self.block_depth += 1;
+        while !matches!(self.peek(0).kind, RightBrace | Eof) {
+            if let Some(statement) = self.declaration() {
+                statements.push(statement);
+            }
+        }
+        self.block_depth -= 1;
+
+        self.consume(RightBrace)?;
+        Ok(statements)
+    }
+
+    fn print_statement(&mut self) -> Result<Stmt, ParserError> {
+        let expr = self.comma()?;
+        self.consume(SemiColon)?;
+        Ok(Stmt::Print(stmt::Print::new(expr)))
+    }
+
+    fn expression_statement(&mut self) -> Result<Stmt, ParserError> {
+        let expr = self.comma()?;
+        self.consume(SemiColon)?;
+        Ok(Stmt::Expression(stmt::Expression::new(expr)))
     }
 
-    pub fn comma(&mut self) -> Result<Expr<'a>, ParserError<'a>> {
+    /// Parses the whole input as a single expression. Errors the parser
+    /// recovered from still fail the parse, earliest first.
+    pub fn parse(&mut self) -> Result<Expr, ParserError> {
+        let result = self.comma().and_then(|expr| {
+            if !self.is_at_end() {
+                return Err(unexpected(self.peek(0)));
+            }
+            Ok(expr)
+        });
+
+        let mut errors = std::mem::take(&mut self.errors);
+        if errors.is_empty() {
+            result
+        } else {
+            Err(errors.remove(0))
+        }
+    }
+
+    pub fn comma(&mut self) -> Result<Expr, ParserError> {
         let mut expr = self.expression()?;
 
         while let Comma = self.peek(0).kind {
@@ -45,18 +343,35 @@ impl<'a> Parser<'a> {
         Ok(expr)
     }
 
-    fn expression(&mut self) -> Result<Expr<'a>, ParserError<'a>> {
-        self.ternary()
+    fn expression(&mut self) -> Result<Expr, ParserError> {
+        self.assignment()
     }
 
-    fn ternary(&mut self) -> Result<Expr<'a>, ParserError<'a>> {
-        let mut expr = self.equality()?;
+    fn assignment(&mut self) -> Result<Expr, ParserError> {
+        let expr = self.ternary()?;
+
+        if let Equal = self.peek(0).kind {
+            let equals = self.advance();
+            let value = self.assignment()?;
+
+            return match expr {
+                Expr::Variable(v) => Ok(Expr::Assign(Assign::new(v.name, value))),
+                Expr::Get(v) => Ok(Expr::Set(Set::new(*v.object, v.name, value))),
+                _ => Err(ParserError::InvalidAssignmentTarget(equals)),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn ternary(&mut self) -> Result<Expr, ParserError> {
+        let mut expr = self.or()?;
 
         while let QuestionMark = self.peek(0).kind {
             let left_operator = self.advance();
-            let middle = self.equality()?;
+            let middle = self.or()?;
             let right_operator = self.consume(TokenKind::Colon)?;
-            let right = self.equality()?;
+            let right = self.or()?;
             expr = Expr::Ternary(Ternary::new(
                 expr,
                 left_operator,
@@ -69,7 +384,31 @@ impl<'a> Parser<'a> {
         Ok(expr)
     }
 
-    fn equality(&mut self) -> Result<Expr<'a>, ParserError<'a>> {
+    fn or(&mut self) -> Result<Expr, ParserError> {
+        let mut expr = self.and()?;
+
+        while let Or = self.peek(0).kind {
+            let operator = self.advance();
+            let right = self.and()?;
+            expr = Expr::Logical(Logical::new(expr, operator, right));
+        }
+
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, ParserError> {
+        let mut expr = self.equality()?;
+
+        while let And = self.peek(0).kind {
+            let operator = self.advance();
+            let right = self.equality()?;
+            expr = Expr::Logical(Logical::new(expr, operator, right));
+        }
+
+        Ok(expr)
+    }
+
+    fn equality(&mut self) -> Result<Expr, ParserError> {
         let mut expr: Expr = self.comparison()?;
 
         while let BangEqual | EqualEqual = self.peek(0).kind {
@@ -81,7 +420,7 @@ impl<'a> Parser<'a> {
         Ok(expr)
     }
 
-    fn comparison(&mut self) -> Result<Expr<'a>, ParserError<'a>> {
+    fn comparison(&mut self) -> Result<Expr, ParserError> {
         let mut expr = self.term()?;
 
         while let Greater | GreaterEqual | Less | LessEqual = self.peek(0).kind {
@@ -93,7 +432,7 @@ impl<'a> Parser<'a> {
         Ok(expr)
     }
 
-    fn term(&mut self) -> Result<Expr<'a>, ParserError<'a>> {
+    fn term(&mut self) -> Result<Expr, ParserError> {
         let mut expr = self.factor()?;
 
         while let Minus | Plus = self.peek(0).kind {
@@ -105,7 +444,7 @@ impl<'a> Parser<'a> {
         Ok(expr)
     }
 
-    fn factor(&mut self) -> Result<Expr<'a>, ParserError<'a>> {
+    fn factor(&mut self) -> Result<Expr, ParserError> {
         let expr = self.unary();
 
         match expr {
@@ -118,92 +457,220 @@ impl<'a> Parser<'a> {
 
                 Ok(expr)
             }
-            Err(ParserError::UnexpectedBinaryOp(op)) => {
+            Err(e @ ParserError::UnexpectedBinaryOp(_)) => {
+                // Report the missing left operand but keep parsing the right
+                // one so later errors in the expression are still found.
+                self.errors.push(e);
                 self.unary()
-                //Err(ParserError::UnexpectedBinaryOp(op))
             }
             Err(v) => Err(v),
         }
     }
 
-    fn unary(&mut self) -> Result<Expr<'a>, ParserError<'a>> {
+    fn unary(&mut self) -> Result<Expr, ParserError> {
         if let Bang | Minus = self.peek(0).kind {
             let operator = self.advance();
             let right = self.unary()?;
             Ok(Expr::Unary(Unary::new(operator, right)))
         } else {
-            self.primary()
+            self.call()
         }
     }
 
-    fn primary(&mut self) -> Result<Expr<'a>, ParserError<'a>> {
+    fn call(&mut self) -> Result<Expr, ParserError> {
+        let mut expr = self.primary()?;
+
+        loop {
+            match self.peek(0).kind {
+                LeftParen => {
+                    self.advance();
+                    expr = self.finish_call(expr)?;
+                }
+                Dot => {
+                    self.advance();
+                    let name = self.consume_identifier()?;
+                    expr = Expr::Get(Get::new(expr, name));
+                }
+                _ => break,
+            }
+        }
+
+        Ok(expr)
+    }
+
+    /// Arguments are parsed with `expression` rather than `comma`, so a `,`
+    /// inside the parentheses separates arguments instead of acting as the
+    /// comma operator. `f((a, b))` still passes a single comma expression.
+    fn finish_call(&mut self, callee: Expr) -> Result<Expr, ParserError> {
+        let mut arguments = Vec::new();
+
+        if self.peek(0).kind != RightParen {
+            loop {
+                if arguments.len() >= MAX_ARGUMENTS {
+                    return Err(ParserError::TooManyArguments(self.peek(0)));
+                }
+                arguments.push(self.expression()?);
+
+                if self.peek(0).kind != Comma {
+                    break;
+                }
+                self.advance();
+            }
+        }
+        let paren = self.consume(RightParen)?;
+
+        Ok(Expr::Call(Call::new(callee, paren, arguments)))
+    }
+
+    fn primary(&mut self) -> Result<Expr, ParserError> {
         let current = self.advance();
 
         match current.kind {
             False | True | Nil => Ok(Expr::Literal(Literal::new(current))),
             Number(_) | String(_) => Ok(Expr::Literal(Literal::new(current))),
+            InterpolationStart(_) => self.interpolation(current),
+            Identifier(_) => Ok(Expr::Variable(Variable::new(current))),
+            TokenKind::This => Ok(Expr::This(expr::This::new(current))),
+            TokenKind::Super => {
+                self.consume(Dot)?;
+                let method = self.consume_identifier()?;
+                Ok(Expr::Super(expr::Super::new(current, method)))
+            }
             LeftParen => {
-                let expr = self.expression()?;
+                let expr = self.comma()?;
                 self.consume(RightParen)?;
                 Ok(Expr::Grouping(Grouping::new(expr)))
             }
             Minus | Plus | Star | Slash | Greater | GreaterEqual | Less | LessEqual | BangEqual
             | EqualEqual => Err(ParserError::UnexpectedBinaryOp(current)),
-            _ => Err(ParserError::UnexpectedToken(current)),
+            _ => Err(unexpected(current)),
+        }
+    }
+
+    /// Parses the rest of an interpolated string after its start token.
+    fn interpolation(&mut self, start: Token) -> Result<Expr, ParserError> {
+        let mut parts = Vec::new();
+        let mut piece = start;
+
+        loop {
+            if let InterpolationStart(text) | InterpolationMiddle(text) | InterpolationEnd(text) =
+                piece.kind
+            {
+                if text != Symbol::EMPTY {
+                    parts.push(Expr::Literal(Literal {
+                        value: LiteralValue::String(text),
+                        span: piece.span,
+                    }));
+                }
+            }
+
+            if let InterpolationEnd(_) = piece.kind {
+                let span = start.span.to(piece.span);
+                return Ok(Expr::Interpolation(Interpolation::new(parts, span)));
+            }
+
+            parts.push(self.expression()?);
+
+            piece = self.peek(0);
+            match piece.kind {
+                InterpolationMiddle(_) | InterpolationEnd(_) => {
+                    self.advance();
+                }
+                Eof => return Err(ParserError::Eof),
+                _ => return Err(unexpected(piece)),
+            }
         }
     }
 
-    fn advance(&mut self) -> Token<'a> {
+    fn advance(&mut self) -> Token {
         if self.is_at_end() {
             self.peek(0)
         } else {
             let current = self.peek(0);
             self.current += 1;
+            if let Error(e) = current.kind {
+                self.errors.push(ParserError::LexError(e));
+            }
             current
         }
     }
 
     fn is_at_end(&self) -> bool {
-        self.current == self.tokens.len() - 1
+        self.peek(0).kind == Eof
     }
 
-    fn peek(&self, pos: i32) -> Token<'a> {
+    fn peek(&self, pos: i32) -> Token {
         let index = self.current + pos as usize;
 
         self.tokens
             .get(index)
             .copied()
-            .expect(format!("index {}", index).as_str())
+            .unwrap_or_else(|| panic!("index {}", index))
     }
 
-    fn consume(&mut self, kind: TokenKind) -> Result<Token<'a>, ParserError<'a>> {
+    fn consume(&mut self, kind: TokenKind) -> Result<Token, ParserError> {
         let current = self.peek(0);
 
         if current.kind == TokenKind::Eof {
             Err(ParserError::Eof)
         } else if current.kind != kind {
-            Err(ParserError::UnexpectedToken(current))
+            Err(unexpected(current))
         } else {
             Ok(self.advance())
         }
     }
 
+    fn consume_identifier(&mut self) -> Result<Token, ParserError> {
+        let current = self.peek(0);
+
+        match current.kind {
+            Identifier(_) => Ok(self.advance()),
+            Eof => Err(ParserError::Eof),
+            _ => Err(unexpected(current)),
+        }
+    }
+
+    /// Discards tokens until the end of the current statement, stopping
+    /// after a `;` or before a token that starts a new statement. Inside a
+    /// block it also stops before the closing `}`.
     fn synchronize(&mut self) {
         use TokenKind::*;
+
+        // `primary` consumes the token it fails on, which may already be the
+        // statement's terminating `;`.
+        if self.current > 0 && self.tokens[self.current - 1].kind == SemiColon {
+            return;
+        }
+
         while !self.is_at_end() {
-            let current = self.advance();
+            match self.peek(0).kind {
+                Class | Fun | Var | For | If | While | Print | Return => return,
+                RightBrace if self.block_depth > 0 => return,
+                _ => {}
+            }
 
-            if let Class | Fun | Var | For | If | While | Print | Return | SemiColon = current.kind
-            {
+            if self.advance().kind == SemiColon {
                 return;
             }
         }
     }
 }
 
+/// The error for finding `token` where it can't appear. A lexical error
+/// token is reported as that error rather than as a syntax error.
+fn unexpected(token: Token) -> ParserError {
+    match token.kind {
+        Error(e) => ParserError::LexError(e),
+        _ => ParserError::UnexpectedToken(token),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::lexer::Lexer;
+    use crate::{
+        lexer::Lexer,
+        token::{SourceId, Span},
+    };
 
     use super::*;
 
@@ -218,4 +685,204 @@ mod tests {
             Err(e) => println!("{:?}", e),
         }
     }
+
+    #[test]
+    fn test_parse_reports_recovered_errors() {
+        let mut parser = Parser::new(Lexer::new("* 2"));
+
+        assert!(matches!(
+            parser.parse(),
+            Err(ParserError::UnexpectedBinaryOp(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_program() {
+        let lexer = Lexer::new("print 1 + 2;\n\"a\";");
+
+        let mut parser = Parser::new(lexer);
+        let (statements, errors) = parser.parse_program();
+        assert!(errors.is_empty());
+
+        assert_eq!(statements.len(), 2);
+        assert_eq!(statements[0].to_string(), "(print (+ 1 2));");
+        assert_eq!(statements[1].to_string(), "a;");
+    }
+
+    #[test]
+    fn test_confusable_identifiers() {
+        let mut parser = Parser::new(Lexer::new("var a = 1; // а\nprint \u{430};"));
+        parser.parse_program();
+
+        let confusables = parser.confusable_identifiers();
+        assert_eq!(confusables.len(), 1);
+        assert_eq!(confusables[0].1.span, Span::new(23, 25));
+    }
+
+    #[test]
+    fn test_parse_interpolation() {
+        let lexer = Lexer::new("print \"a ${b} ${1 + 2}\";\nprint \"${}\";");
+
+        let (statements, errors) = Parser::new(lexer).parse_program();
+
+        assert_eq!(
+            statements[0].to_string(),
+            "(print (interpolate a  b   (+ 1 2)));"
+        );
+        assert!(matches!(
+            errors.as_slice(),
+            [ParserError::UnexpectedToken(_)]
+        ));
+    }
+
+    #[test]
+    fn test_reports_lex_errors() {
+        let lexer = Lexer::new("var a = 1 @;\nprint ;\nprint a $;\nprint \"a\\q\";\nprint 1;");
+
+        let (statements, errors) = Parser::new(lexer).parse_program();
+
+        assert_eq!(statements.len(), 1);
+        assert!(matches!(
+            errors.as_slice(),
+            [
+                ParserError::LexError(LexError::UnexpectedCharacter('@', _)),
+                ParserError::UnexpectedToken(_),
+                ParserError::LexError(LexError::UnexpectedCharacter('$', _)),
+                ParserError::LexError(LexError::InvalidEscape(..)),
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_parse_declarations() {
+        let lexer = Lexer::new("var a = 1; { var b; a = b = 2; }");
+
+        let mut parser = Parser::new(lexer);
+        let (statements, errors) = parser.parse_program();
+        assert!(errors.is_empty());
+
+        assert_eq!(statements[0].to_string(), "(var a 1);");
+        assert_eq!(statements[1].to_string(), "{ (var b); (= a (= b 2)); }");
+    }
+
+    #[test]
+    fn test_parse_control_flow() {
+        let lexer = Lexer::new("if (a or b and c) print 1; else while (a) a = false;");
+
+        let mut parser = Parser::new(lexer);
+        let (statements, errors) = parser.parse_program();
+        assert!(errors.is_empty());
+
+        assert_eq!(
+            statements[0].to_string(),
+            "(if (or a (and b c)) (print 1); else (while a (= a false);))"
+        );
+    }
+
+    #[test]
+    fn test_for_desugaring() {
+        let lexer = Lexer::new("for (var i = 0; i < 3; i = i + 1) print i;");
+
+        let mut parser = Parser::new(lexer);
+        let (statements, errors) = parser.parse_program();
+        assert!(errors.is_empty());
+
+        assert_eq!(
+            statements[0].to_string(),
+            "{ (var i 0); (while (< i 3) { (print i); (= i (+ i 1)); }) }"
+        );
+    }
+
+    #[test]
+    fn test_parse_functions() {
+        let lexer = Lexer::new("fun add(a, b) { return a + b; } print add(1, (2, 3))(4);");
+
+        let mut parser = Parser::new(lexer);
+        let (statements, errors) = parser.parse_program();
+        assert!(errors.is_empty());
+
+        assert_eq!(
+            statements[0].to_string(),
+            "(fun add (a b) { (return (+ a b)); })"
+        );
+        assert_eq!(
+            statements[1].to_string(),
+            "(print (call (call add 1 (group (, 2 3))) 4));"
+        );
+    }
+
+    #[test]
+    fn test_parse_classes() {
+        let lexer =
+            Lexer::new("class B < A { init(x) { this.x = x; } get() { return super.get(); } }");
+
+        let mut parser = Parser::new(lexer);
+        let (statements, errors) = parser.parse_program();
+        assert!(errors.is_empty());
+
+        assert_eq!(
+            statements[0].to_string(),
+            "(class B < A (fun init (x) { (= (. this x) x); }) (fun get () { (return (call (. super get))); }))"
+        );
+    }
+
+    #[test]
+    fn test_expression_spans() {
+        let source = SourceId::new(3);
+        let expr = Parser::new(Lexer::with_source("x = (a.b(1) + 2)", source))
+            .parse()
+            .unwrap();
+
+        assert_eq!(expr.span(), Span::new(0, 15).with_source(source));
+        let Expr::Assign(assign) = expr else {
+            panic!("expected assignment");
+        };
+        assert_eq!(assign.value.span(), Span::new(5, 15).with_source(source));
+    }
+
+    #[test]
+    fn test_invalid_assignment_target() {
+        let lexer = Lexer::new("1 + a = 2;");
+
+        let mut parser = Parser::new(lexer);
+        assert!(matches!(
+            parser.parse_program().1.as_slice(),
+            [ParserError::InvalidAssignmentTarget(_)]
+        ));
+    }
+
+    #[test]
+    fn test_missing_semicolon() {
+        let lexer = Lexer::new("print 1");
+
+        let mut parser = Parser::new(lexer);
+        assert!(matches!(
+            parser.parse_program().1.as_slice(),
+            [ParserError::Eof]
+        ));
+    }
+
+    #[test]
+    fn test_reports_every_error() {
+        let lexer = Lexer::new(
+            "var = 1;\nprint 1 +;\n{ var ok = 2; print (; }\nfun f( {}\nprint * 2;\nprint 3;",
+        );
+
+        let mut parser = Parser::new(lexer);
+        let (statements, errors) = parser.parse_program();
+
+        assert!(matches!(
+            errors.as_slice(),
+            [
+                ParserError::UnexpectedToken(_),
+                ParserError::UnexpectedToken(_),
+                ParserError::UnexpectedToken(_),
+                ParserError::UnexpectedToken(_),
+                ParserError::UnexpectedBinaryOp(_),
+            ]
+        ));
+        assert_eq!(statements.len(), 3);
+        assert_eq!(statements[0].to_string(), "{ (var ok 2); }");
+        assert_eq!(statements[2].to_string(), "(print 3);");
+    }
 }
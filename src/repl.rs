@@ -0,0 +1,288 @@
+use rustyline::{error::ReadlineError, DefaultEditor};
+
+use crate::{
+    diagnostics::SourceMap,
+    interpreter::{Interpreter, Output},
+    lexer::{LexError, Lexer},
+    lox::{self, GcOptions, LoxError},
+    optimizer::{self, OptLevel},
+    parser::Parser,
+    resolver::Resolver,
+    stmt::{self, Stmt},
+    token::{SourceId, TokenKind},
+};
+
+const PROMPT: &str = "> ";
+const CONTINUATION_PROMPT: &str = "... ";
+const HISTORY_FILE: &str = ".jlox_history";
+
+const HELP: &str = "\
+:quit         exit the REPL
+:reset        discard every definition made so far
+:load <file>  run a file in the current session
+:help         show this message";
+
+#[derive(Debug, PartialEq)]
+enum Command<'a> {
+    Quit,
+    Reset,
+    Load(&'a str),
+    Help,
+    Unknown(&'a str),
+}
+
+impl<'a> Command<'a> {
+    fn parse(line: &'a str) -> Option<Self> {
+        let line = line.trim().strip_prefix(':')?;
+        let (name, argument) = line
+            .split_once(char::is_whitespace)
+            .map_or((line, ""), |(name, argument)| (name, argument.trim()));
+
+        Some(match name {
+            "quit" | "q" => Command::Quit,
+            "reset" => Command::Reset,
+            "load" if !argument.is_empty() => Command::Load(argument),
+            "help" => Command::Help,
+            _ => Command::Unknown(line),
+        })
+    }
+}
+
+/// Interactive session. Definitions persist between entries until `:reset`.
+///
+/// Every entry and loaded file gets its own source id, so an error inside a
+/// function defined several entries ago still points at the right text.
+pub struct Repl {
+    interpreter: Interpreter,
+    sources: SourceMap,
+    opt: OptLevel,
+    gc: GcOptions,
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Repl {
+            interpreter: Interpreter::new(),
+            sources: SourceMap::new(),
+            opt: OptLevel::default(),
+            gc: GcOptions::default(),
+        }
+    }
+
+    pub fn with_opt_level(self, opt: OptLevel) -> Self {
+        Repl { opt, ..self }
+    }
+
+    pub fn with_gc(mut self, gc: GcOptions) -> Self {
+        self.interpreter.set_gc_stress(gc.stress);
+        Repl { gc, ..self }
+    }
+
+    pub fn run(&mut self) -> Result<(), LoxError> {
+        let mut editor = DefaultEditor::new().map_err(io_error)?;
+        let history =
+            std::env::var_os("HOME").map(|home| std::path::Path::new(&home).join(HISTORY_FILE));
+        if let Some(history) = &history {
+            let _ = editor.load_history(history);
+        }
+
+        let mut entry = String::new();
+
+        loop {
+            let prompt = if entry.is_empty() {
+                PROMPT
+            } else {
+                CONTINUATION_PROMPT
+            };
+
+            let line = match editor.readline(prompt) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => {
+                    entry.clear();
+                    continue;
+                }
+                Err(ReadlineError::Eof) => break,
+                Err(e) => return Err(io_error(e)),
+            };
+
+            if entry.is_empty() {
+                if let Some(command) = Command::parse(&line) {
+                    let _ = editor.add_history_entry(line.as_str());
+                    match command {
+                        Command::Quit => break,
+                        Command::Reset => {
+                            *self = Repl::new().with_opt_level(self.opt).with_gc(self.gc)
+                        }
+                        Command::Load(path) => {
+                            if let Err(e) = self.load(path) {
+                                eprintln!("{}", e);
+                            }
+                        }
+                        Command::Help => println!("{}", HELP),
+                        Command::Unknown(name) => {
+                            eprintln!("Unknown command ':{}', try :help", name)
+                        }
+                    }
+                    continue;
+                }
+            }
+
+            entry.push_str(&line);
+            entry.push('\n');
+            if !is_complete(&entry) {
+                continue;
+            }
+
+            let _ = editor.add_history_entry(entry.trim_end());
+            if entry.trim().is_empty() {
+                entry.clear();
+                continue;
+            }
+
+            match self.eval(&std::mem::take(&mut entry)) {
+                Ok(Some(value)) => println!("{}", value),
+                Ok(None) => {}
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+
+        if let Some(history) = &history {
+            let _ = editor.save_history(history);
+        }
+        if self.gc.stats {
+            eprintln!("{}", self.interpreter.gc_stats());
+        }
+
+        Ok(())
+    }
+
+    /// Runs one entry. A bare expression evaluates to its value, anything
+    /// else is run as a program.
+    pub fn eval(&mut self, input: &str) -> Result<Option<Output>, LoxError> {
+        let source = self.sources.add("<repl>", input.to_owned());
+
+        self.eval_source(source)
+            .map_err(|e| e.with_sources(&self.sources))
+    }
+
+    fn eval_source(&mut self, source: SourceId) -> Result<Option<Output>, LoxError> {
+        let file = self.sources.get(source).expect("entry was just added");
+
+        let mut parser = Parser::new(Lexer::with_source(file.text(), source));
+        let Ok(expr) = parser.parse() else {
+            lox::run(&mut self.interpreter, file, source, self.opt)?;
+            return Ok(None);
+        };
+        lox::warn_confusables(&parser, &file);
+
+        let mut statements = vec![Stmt::Expression(stmt::Expression::new(expr))];
+        Resolver::new().resolve(&mut statements)?;
+
+        let Some(Stmt::Expression(statement)) = optimizer::optimize(statements, self.opt).pop()
+        else {
+            unreachable!("resolver and optimizer keep the statement they were given");
+        };
+
+        Ok(Some(self.interpreter.interpret(&statement.expr)?))
+    }
+
+    fn load(&mut self, path: &str) -> Result<(), LoxError> {
+        let source = self.sources.add(path, std::fs::read_to_string(path)?);
+
+        let file = self.sources.get(source).expect("file was just added");
+
+        lox::run(&mut self.interpreter, file, source, self.opt)
+            .map_err(|e| e.with_sources(&self.sources))
+    }
+}
+
+/// Whether `input` can be run as is, or needs more lines because a bracket
+/// string or block comment is still open.
+fn is_complete(input: &str) -> bool {
+    let mut depth = 0i32;
+
+    for token in Lexer::new(input) {
+        match token.kind {
+            TokenKind::LeftParen | TokenKind::LeftBrace | TokenKind::InterpolationStart(_) => {
+                depth += 1
+            }
+            TokenKind::RightParen | TokenKind::RightBrace | TokenKind::InterpolationEnd(_) => {
+                depth -= 1
+            }
+            TokenKind::Error(
+                LexError::UnterminatedString(_) | LexError::UnterminatedComment(_),
+            ) => return false,
+            _ => {}
+        }
+    }
+
+    depth <= 0
+}
+
+fn io_error(error: ReadlineError) -> LoxError {
+    LoxError::IoError(std::io::Error::other(error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_complete() {
+        assert!(is_complete("print 1;"));
+        assert!(!is_complete("fun f() {"));
+        assert!(!is_complete("fun f() {\n  print (1 +"));
+        assert!(is_complete("fun f() {\n  print (1 + 2);\n}"));
+        assert!(!is_complete("print \"a"));
+        assert!(!is_complete("/* a"));
+        assert!(!is_complete("print \"${a +"));
+    }
+
+    #[test]
+    fn test_command() {
+        assert_eq!(Command::parse(":quit"), Some(Command::Quit));
+        assert_eq!(
+            Command::parse(" :load  a.lox "),
+            Some(Command::Load("a.lox"))
+        );
+        assert_eq!(Command::parse(":load"), Some(Command::Unknown("load")));
+        assert_eq!(Command::parse("print 1;"), None);
+    }
+
+    #[test]
+    fn test_session_persists() {
+        let mut repl = Repl::new();
+
+        assert_eq!(repl.eval("var a = 1;").unwrap(), None);
+        assert!(repl.eval("b").is_err());
+        assert_eq!(repl.eval("fun f(x) { return a + x; }").unwrap(), None);
+        assert_eq!(repl.eval("f(2)").unwrap(), Some(Output::Number(3.0)));
+
+        repl = Repl::new();
+        assert!(repl.eval("a").is_err());
+    }
+
+    #[test]
+    fn test_rejects_recovered_parse_errors() {
+        let mut repl = Repl::new();
+
+        assert!(repl.eval("* 2").is_err());
+    }
+
+    #[test]
+    fn test_errors_point_into_their_entry() {
+        let mut repl = Repl::new();
+
+        repl.eval("fun half(x) {\n  return x / 0;\n}").unwrap();
+        repl.eval("var a = 1;").unwrap();
+        let error = repl.eval("half(a)").unwrap_err();
+
+        assert!(error.to_string().contains("2 |   return x / 0;"));
+    }
+}
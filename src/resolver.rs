@@ -0,0 +1,333 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    expr::*,
+    intern::Symbol,
+    stmt::{self, Stmt},
+    token::Token,
+    visit::{
+        walk_assign_mut, walk_block_mut, walk_function_mut, walk_return_mut, walk_stmt_mut,
+        walk_stmts_mut, walk_var_mut, VisitorMut,
+    },
+};
+
+#[derive(Debug)]
+pub enum ResolverError {
+    ReadInOwnInitializer(Token),
+    AlreadyDeclared(Token),
+    TopLevelReturn(Token),
+    ReturnFromInitializer(Token),
+    ThisOutsideClass(Token),
+    SuperOutsideClass(Token),
+    SuperWithoutSuperclass(Token),
+    InheritsFromItself(Token),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FunctionKind {
+    None,
+    Function,
+    Method,
+    Initializer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ClassKind {
+    None,
+    Class,
+    Subclass,
+}
+
+#[derive(Debug)]
+struct Local {
+    slot: usize,
+    defined: bool,
+}
+
+/// Static pass run between the parser and the interpreter. It binds every
+/// local variable reference to a `Binding` and collects scope errors.
+#[derive(Debug)]
+pub struct Resolver {
+    scopes: Vec<HashMap<Symbol, Local>>,
+    function: FunctionKind,
+    class: ClassKind,
+    errors: Vec<ResolverError>,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Resolver {
+            scopes: Vec::new(),
+            function: FunctionKind::None,
+            class: ClassKind::None,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn resolve(mut self, statements: &mut [Stmt]) -> Result<(), Vec<ResolverError>> {
+        walk_stmts_mut(&mut self, statements);
+
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn resolve_function(&mut self, function: &mut stmt::Function, kind: FunctionKind) {
+        let enclosing_function = self.function;
+        self.function = kind;
+
+        self.begin_scope();
+        for param in &function.params {
+            self.declare(param);
+            self.define(param);
+        }
+        walk_function_mut(self, function);
+        self.end_scope();
+
+        self.function = enclosing_function;
+    }
+
+    fn resolve_local(&self, name: &Token) -> Option<Binding> {
+        let key = name.symbol();
+
+        self.scopes
+            .iter()
+            .rev()
+            .enumerate()
+            .find_map(|(depth, scope)| {
+                scope.get(&key).map(|local| Binding {
+                    depth,
+                    slot: local.slot,
+                })
+            })
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Token) {
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+
+        let key = name.symbol();
+        if scope.contains_key(&key) {
+            self.errors.push(ResolverError::AlreadyDeclared(*name));
+            return;
+        }
+
+        let slot = scope.len();
+        scope.insert(
+            key,
+            Local {
+                slot,
+                defined: false,
+            },
+        );
+    }
+
+    fn define(&mut self, name: &Token) {
+        if let Some(local) = self
+            .scopes
+            .last_mut()
+            .and_then(|scope| scope.get_mut(&name.symbol()))
+        {
+            local.defined = true;
+        }
+    }
+
+    /// Declares a defined name that has no source token, such as `this`.
+    fn declare_name(&mut self, name: Symbol) {
+        if let Some(scope) = self.scopes.last_mut() {
+            let slot = scope.len();
+            scope.insert(
+                name,
+                Local {
+                    slot,
+                    defined: true,
+                },
+            );
+        }
+    }
+}
+
+impl VisitorMut for Resolver {
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        match stmt {
+            Stmt::Function(v) => {
+                self.declare(&v.name);
+                self.define(&v.name);
+                self.resolve_function(Rc::make_mut(v), FunctionKind::Function);
+            }
+            _ => walk_stmt_mut(self, stmt),
+        }
+    }
+
+    fn visit_var_mut(&mut self, stmt: &mut stmt::Var) {
+        self.declare(&stmt.name);
+        walk_var_mut(self, stmt);
+        self.define(&stmt.name);
+    }
+
+    fn visit_block_mut(&mut self, stmt: &mut stmt::Block) {
+        self.begin_scope();
+        walk_block_mut(self, stmt);
+        self.end_scope();
+    }
+
+    fn visit_return_mut(&mut self, stmt: &mut stmt::Return) {
+        if self.function == FunctionKind::None {
+            self.errors
+                .push(ResolverError::TopLevelReturn(stmt.keyword));
+        }
+
+        if stmt.value.is_some() && self.function == FunctionKind::Initializer {
+            self.errors
+                .push(ResolverError::ReturnFromInitializer(stmt.keyword));
+        }
+        walk_return_mut(self, stmt);
+    }
+
+    fn visit_class_mut(&mut self, stmt: &mut stmt::Class) {
+        let enclosing_class = self.class;
+        self.class = ClassKind::Class;
+
+        self.declare(&stmt.name);
+        self.define(&stmt.name);
+
+        if let Some(superclass) = &mut stmt.superclass {
+            if superclass.name.symbol() == stmt.name.symbol() {
+                self.errors
+                    .push(ResolverError::InheritsFromItself(superclass.name));
+            }
+
+            self.class = ClassKind::Subclass;
+            superclass.binding = self.resolve_local(&superclass.name);
+
+            self.begin_scope();
+            self.declare_name(Symbol::SUPER);
+        }
+
+        self.begin_scope();
+        self.declare_name(Symbol::THIS);
+
+        for method in &mut stmt.methods {
+            let kind = if method.name.symbol() == Symbol::INIT {
+                FunctionKind::Initializer
+            } else {
+                FunctionKind::Method
+            };
+            self.resolve_function(Rc::make_mut(method), kind);
+        }
+
+        self.end_scope();
+
+        if stmt.superclass.is_some() {
+            self.end_scope();
+        }
+
+        self.class = enclosing_class;
+    }
+
+    fn visit_variable_mut(&mut self, expr: &mut Variable) {
+        if let Some(scope) = self.scopes.last() {
+            if let Some(Local { defined: false, .. }) = scope.get(&expr.name.symbol()) {
+                self.errors
+                    .push(ResolverError::ReadInOwnInitializer(expr.name));
+            }
+        }
+        expr.binding = self.resolve_local(&expr.name);
+    }
+
+    fn visit_assign_mut(&mut self, expr: &mut Assign) {
+        walk_assign_mut(self, expr);
+        expr.binding = self.resolve_local(&expr.name);
+    }
+
+    fn visit_this_mut(&mut self, expr: &mut This) {
+        if self.class == ClassKind::None {
+            self.errors
+                .push(ResolverError::ThisOutsideClass(expr.keyword));
+        } else {
+            expr.binding = self.resolve_local(&expr.keyword);
+        }
+    }
+
+    fn visit_super_mut(&mut self, expr: &mut Super) {
+        match self.class {
+            ClassKind::None => self
+                .errors
+                .push(ResolverError::SuperOutsideClass(expr.keyword)),
+            ClassKind::Class => self
+                .errors
+                .push(ResolverError::SuperWithoutSuperclass(expr.keyword)),
+            ClassKind::Subclass => expr.binding = self.resolve_local(&expr.keyword),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{lexer::Lexer, parser::Parser};
+
+    use super::*;
+
+    fn resolve(src: &str) -> Result<Vec<Stmt>, Vec<ResolverError>> {
+        let (mut statements, errors) = Parser::new(Lexer::new(src)).parse_program();
+        assert!(errors.is_empty());
+        Resolver::new().resolve(&mut statements)?;
+        Ok(statements)
+    }
+
+    #[test]
+    fn test_bindings() {
+        let statements = resolve("{ var a = 1; var b = 2; fun f() { return b; } }").unwrap();
+
+        let Stmt::Block(block) = &statements[0] else {
+            panic!("expected block");
+        };
+        let Stmt::Function(function) = &block.statements[2] else {
+            panic!("expected function");
+        };
+        let Stmt::Return(stmt::Return {
+            value: Some(Expr::Variable(variable)),
+            ..
+        }) = &function.body[0]
+        else {
+            panic!("expected return");
+        };
+
+        assert_eq!(variable.binding, Some(Binding { depth: 1, slot: 1 }));
+    }
+
+    #[test]
+    fn test_collects_every_error() {
+        let src = "return 1; { var a = a; var b; var b; } print this; class A { f() { super.f(); } } class B < B {}";
+        let errors = resolve(src).unwrap_err();
+
+        assert!(matches!(
+            errors.as_slice(),
+            [
+                ResolverError::TopLevelReturn(_),
+                ResolverError::ReadInOwnInitializer(_),
+                ResolverError::AlreadyDeclared(_),
+                ResolverError::ThisOutsideClass(_),
+                ResolverError::SuperWithoutSuperclass(_),
+                ResolverError::InheritsFromItself(_),
+            ]
+        ));
+    }
+}
@@ -0,0 +1,241 @@
+use std::rc::Rc;
+
+use crate::{
+    expr::{Expr, Variable},
+    token::Token,
+};
+
+#[derive(Debug, Clone)]
+pub enum Stmt {
+    Expression(Expression),
+    Print(Print),
+    Var(Var),
+    Block(Block),
+    If(If),
+    While(While),
+    /// Shared so that functions created from the declaration can outlive
+    /// the tree it was parsed into.
+    Function(Rc<Function>),
+    Return(Return),
+    Class(Class),
+}
+
+#[derive(Debug, Clone)]
+pub struct Expression {
+    pub expr: Expr,
+}
+
+#[derive(Debug, Clone)]
+pub struct Print {
+    pub expr: Expr,
+}
+
+#[derive(Debug, Clone)]
+pub struct Var {
+    pub name: Token,
+    pub initializer: Option<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub statements: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone)]
+pub struct If {
+    pub condition: Expr,
+    pub then_branch: Box<Stmt>,
+    pub else_branch: Option<Box<Stmt>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct While {
+    pub condition: Expr,
+    pub body: Box<Stmt>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: Token,
+    pub params: Vec<Token>,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Return {
+    pub keyword: Token,
+    pub value: Option<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Class {
+    pub name: Token,
+    pub superclass: Option<Variable>,
+    pub methods: Vec<Rc<Function>>,
+}
+
+impl Expression {
+    pub fn new(expr: Expr) -> Self {
+        Expression { expr }
+    }
+}
+
+impl Print {
+    pub fn new(expr: Expr) -> Self {
+        Print { expr }
+    }
+}
+
+impl Var {
+    pub fn new(name: Token, initializer: Option<Expr>) -> Self {
+        Var { name, initializer }
+    }
+}
+
+impl Block {
+    pub fn new(statements: Vec<Stmt>) -> Self {
+        Block { statements }
+    }
+}
+
+impl If {
+    pub fn new(condition: Expr, then_branch: Stmt, else_branch: Option<Stmt>) -> Self {
+        If {
+            condition,
+            then_branch: Box::new(then_branch),
+            else_branch: else_branch.map(Box::new),
+        }
+    }
+}
+
+impl While {
+    pub fn new(condition: Expr, body: Stmt) -> Self {
+        While {
+            condition,
+            body: Box::new(body),
+        }
+    }
+}
+
+impl Function {
+    pub fn new(name: Token, params: Vec<Token>, body: Vec<Stmt>) -> Self {
+        Function { name, params, body }
+    }
+}
+
+impl Return {
+    pub fn new(keyword: Token, value: Option<Expr>) -> Self {
+        Return { keyword, value }
+    }
+}
+
+impl Class {
+    pub fn new(name: Token, superclass: Option<Variable>, methods: Vec<Rc<Function>>) -> Self {
+        Class {
+            name,
+            superclass,
+            methods,
+        }
+    }
+}
+
+impl std::fmt::Display for Stmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stmt::Expression(v) => write!(f, "{}", v),
+            Stmt::Print(v) => write!(f, "{}", v),
+            Stmt::Var(v) => write!(f, "{}", v),
+            Stmt::Block(v) => write!(f, "{}", v),
+            Stmt::If(v) => write!(f, "{}", v),
+            Stmt::While(v) => write!(f, "{}", v),
+            Stmt::Function(v) => write!(f, "{}", v),
+            Stmt::Return(v) => write!(f, "{}", v),
+            Stmt::Class(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+impl std::fmt::Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{};", self.expr)
+    }
+}
+
+impl std::fmt::Display for Print {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(print {});", self.expr)
+    }
+}
+
+impl std::fmt::Display for Var {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.initializer {
+            Some(v) => write!(f, "(var {} {});", self.name, v),
+            None => write!(f, "(var {});", self.name),
+        }
+    }
+}
+
+impl std::fmt::Display for Block {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{")?;
+        for statement in &self.statements {
+            write!(f, " {}", statement)?;
+        }
+        write!(f, " }}")
+    }
+}
+
+impl std::fmt::Display for If {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.else_branch {
+            Some(v) => write!(f, "(if {} {} else {})", self.condition, self.then_branch, v),
+            None => write!(f, "(if {} {})", self.condition, self.then_branch),
+        }
+    }
+}
+
+impl std::fmt::Display for While {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(while {} {})", self.condition, self.body)
+    }
+}
+
+impl std::fmt::Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(fun {} (", self.name)?;
+        for (i, param) in self.params.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", param)?;
+        }
+        write!(f, ") {{")?;
+        for statement in &self.body {
+            write!(f, " {}", statement)?;
+        }
+        write!(f, " }})")
+    }
+}
+
+impl std::fmt::Display for Return {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.value {
+            Some(v) => write!(f, "(return {});", v),
+            None => write!(f, "(return);"),
+        }
+    }
+}
+
+impl std::fmt::Display for Class {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(class {}", self.name)?;
+        if let Some(superclass) = &self.superclass {
+            write!(f, " < {}", superclass)?;
+        }
+        for method in &self.methods {
+            write!(f, " {}", method)?;
+        }
+        write!(f, ")")
+    }
+}
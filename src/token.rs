@@ -1,5 +1,7 @@
+use crate::{intern::Symbol, lexer::LexError};
+
 #[derive(Debug, PartialEq, Clone, Copy)]
-pub enum TokenKind<'a> {
+pub enum TokenKind {
     // one char tokens
     LeftParen,
     RightParen,
@@ -26,8 +28,14 @@ pub enum TokenKind<'a> {
     LessEqual,
 
     // literals
-    Identifier(&'a str),
-    String(&'a str),
+    Identifier(Symbol),
+    String(Symbol),
+    /// The text of `"a ${` in an interpolated string.
+    InterpolationStart(Symbol),
+    /// The text of `} b ${` between two interpolated expressions.
+    InterpolationMiddle(Symbol),
+    /// The text of `} c"` after the last interpolated expression.
+    InterpolationEnd(Symbol),
     Number(f64),
 
     //keywords
@@ -49,36 +57,100 @@ pub enum TokenKind<'a> {
     While,
     Eof,
     Skip(char),
-    Error(&'a str),
+    Error(LexError),
 
-    Comment(&'a str),
+    Comment,
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
-pub struct Token<'a> {
-    pub kind: TokenKind<'a>,
+pub struct Token {
+    pub kind: TokenKind,
     pub span: Span,
 }
 
+/// Identifies the text a `Span` points into: a script, a loaded file or a
+/// single REPL entry. Spans from different sources can live in one session
+/// and are told apart by this id.
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct SourceId(u32);
+
+impl SourceId {
+    pub fn new(index: usize) -> Self {
+        SourceId(index as u32)
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Span {
+    source: SourceId,
     start: usize,
     end: usize,
 }
 
 impl Span {
+    /// A span in the default source, which is all a single script needs.
     pub fn new(start: usize, end: usize) -> Self {
-        Span { start, end }
+        Span {
+            source: SourceId::default(),
+            start,
+            end,
+        }
+    }
+
+    pub fn with_source(self, source: SourceId) -> Self {
+        Span { source, ..self }
+    }
+
+    pub fn source(&self) -> SourceId {
+        self.source
+    }
+
+    /// The span from the start of `self` to the end of `end`.
+    pub fn to(self, end: Span) -> Self {
+        Span {
+            end: end.end.max(self.start),
+            ..self
+        }
+    }
+
+    /// Byte offset of the first byte covered by the span.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Byte offset one past the last byte covered by the span.
+    pub fn end(&self) -> usize {
+        self.end
     }
 }
 
-impl<'a> Token<'a> {
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+impl Token {
     pub fn new(kind: TokenKind, span: Span) -> Token {
         Token { kind, span }
     }
+
+    /// The interned name of an identifier, `this` or `super` token.
+    pub fn symbol(&self) -> Symbol {
+        match self.kind {
+            TokenKind::Identifier(v) => v,
+            TokenKind::This => Symbol::THIS,
+            TokenKind::Super => Symbol::SUPER,
+            _ => Symbol::intern(&self.to_string()),
+        }
+    }
 }
 
-impl<'a> std::fmt::Display for Token<'a> {
+impl std::fmt::Display for Token {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.kind {
             TokenKind::LeftParen => write!(f, "("),
@@ -89,7 +161,7 @@ impl<'a> std::fmt::Display for Token<'a> {
             TokenKind::Dot => write!(f, "."),
             TokenKind::Minus => write!(f, "-"),
             TokenKind::Plus => write!(f, "+"),
-            TokenKind::SemiColon => write!(f, ","),
+            TokenKind::SemiColon => write!(f, ";"),
             TokenKind::Slash => write!(f, "/"),
             TokenKind::Star => write!(f, "*"),
             TokenKind::QuestionMark => write!(f, "?"),
@@ -104,6 +176,9 @@ impl<'a> std::fmt::Display for Token<'a> {
             TokenKind::LessEqual => write!(f, "<="),
             TokenKind::Identifier(v) => write!(f, "{}", v),
             TokenKind::String(v) => write!(f, "{}", v),
+            TokenKind::InterpolationStart(v) => write!(f, "\"{}${{", v),
+            TokenKind::InterpolationMiddle(v) => write!(f, "}}{}${{", v),
+            TokenKind::InterpolationEnd(v) => write!(f, "}}{}\"", v),
             TokenKind::Number(v) => write!(f, "{}", v),
             TokenKind::And => write!(f, "and"),
             TokenKind::Class => write!(f, "class"),
@@ -121,7 +196,7 @@ impl<'a> std::fmt::Display for Token<'a> {
             TokenKind::True => write!(f, "true"),
             TokenKind::Var => write!(f, "var"),
             TokenKind::While => write!(f, "while"),
-            TokenKind::Comment(v) => write!(f, "{}", v),
+            TokenKind::Comment => write!(f, "comment"),
             TokenKind::Eof => write!(f, "eof"),
             TokenKind::Skip(v) => write!(f, "skip {}", v),
             TokenKind::Error(v) => write!(f, "error {}", v),
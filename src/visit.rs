@@ -0,0 +1,838 @@
+//! Generic traversals over the syntax tree.
+//!
+//! `Visitor` walks a tree by reference, `VisitorMut` walks it in place and
+//! `Fold` takes it apart and rebuilds it. Each trait method defaults to the
+//! free function of the same node name (`walk_*` for the visitors, `fold_*`
+//! for folds), which recurses into the node's children. An implementation
+//! overrides only the nodes it cares about and calls that function itself
+//! when it still wants the children visited.
+//!
+//! The resolver is built on `VisitorMut` and the optimizer on `Fold`.
+//! Nothing in the interpreter only reads the tree, so `Visitor` and its
+//! `walk_*` functions are left for linters and allowed to go unused.
+
+// `Visitor` and the `walk_*` functions have no caller in the interpreter.
+#![allow(dead_code)]
+
+use std::rc::Rc;
+
+use crate::{
+    expr::*,
+    stmt::{self, Stmt},
+};
+
+pub trait Visitor: Sized {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        walk_stmt(self, stmt)
+    }
+
+    fn visit_expression(&mut self, stmt: &stmt::Expression) {
+        walk_expression(self, stmt)
+    }
+
+    fn visit_print(&mut self, stmt: &stmt::Print) {
+        walk_print(self, stmt)
+    }
+
+    fn visit_var(&mut self, stmt: &stmt::Var) {
+        walk_var(self, stmt)
+    }
+
+    fn visit_block(&mut self, stmt: &stmt::Block) {
+        walk_block(self, stmt)
+    }
+
+    fn visit_if(&mut self, stmt: &stmt::If) {
+        walk_if(self, stmt)
+    }
+
+    fn visit_while(&mut self, stmt: &stmt::While) {
+        walk_while(self, stmt)
+    }
+
+    fn visit_function(&mut self, stmt: &stmt::Function) {
+        walk_function(self, stmt)
+    }
+
+    fn visit_return(&mut self, stmt: &stmt::Return) {
+        walk_return(self, stmt)
+    }
+
+    fn visit_class(&mut self, stmt: &stmt::Class) {
+        walk_class(self, stmt)
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr)
+    }
+
+    fn visit_literal(&mut self, _expr: &Literal) {}
+
+    fn visit_unary(&mut self, expr: &Unary) {
+        walk_unary(self, expr)
+    }
+
+    fn visit_binary(&mut self, expr: &Binary) {
+        walk_binary(self, expr)
+    }
+
+    fn visit_grouping(&mut self, expr: &Grouping) {
+        walk_grouping(self, expr)
+    }
+
+    fn visit_ternary(&mut self, expr: &Ternary) {
+        walk_ternary(self, expr)
+    }
+
+    fn visit_variable(&mut self, _expr: &Variable) {}
+
+    fn visit_assign(&mut self, expr: &Assign) {
+        walk_assign(self, expr)
+    }
+
+    fn visit_logical(&mut self, expr: &Logical) {
+        walk_logical(self, expr)
+    }
+
+    fn visit_call(&mut self, expr: &Call) {
+        walk_call(self, expr)
+    }
+
+    fn visit_get(&mut self, expr: &Get) {
+        walk_get(self, expr)
+    }
+
+    fn visit_set(&mut self, expr: &Set) {
+        walk_set(self, expr)
+    }
+
+    fn visit_this(&mut self, _expr: &This) {}
+
+    fn visit_super(&mut self, _expr: &Super) {}
+
+    fn visit_interpolation(&mut self, expr: &Interpolation) {
+        walk_interpolation(self, expr)
+    }
+}
+
+pub fn walk_stmts<V: Visitor>(visitor: &mut V, statements: &[Stmt]) {
+    for statement in statements {
+        visitor.visit_stmt(statement);
+    }
+}
+
+pub fn walk_stmt<V: Visitor>(visitor: &mut V, stmt: &Stmt) {
+    match stmt {
+        Stmt::Expression(v) => visitor.visit_expression(v),
+        Stmt::Print(v) => visitor.visit_print(v),
+        Stmt::Var(v) => visitor.visit_var(v),
+        Stmt::Block(v) => visitor.visit_block(v),
+        Stmt::If(v) => visitor.visit_if(v),
+        Stmt::While(v) => visitor.visit_while(v),
+        Stmt::Function(v) => visitor.visit_function(v),
+        Stmt::Return(v) => visitor.visit_return(v),
+        Stmt::Class(v) => visitor.visit_class(v),
+    }
+}
+
+pub fn walk_expression<V: Visitor>(visitor: &mut V, stmt: &stmt::Expression) {
+    visitor.visit_expr(&stmt.expr)
+}
+
+pub fn walk_print<V: Visitor>(visitor: &mut V, stmt: &stmt::Print) {
+    visitor.visit_expr(&stmt.expr)
+}
+
+pub fn walk_var<V: Visitor>(visitor: &mut V, stmt: &stmt::Var) {
+    if let Some(initializer) = &stmt.initializer {
+        visitor.visit_expr(initializer);
+    }
+}
+
+pub fn walk_block<V: Visitor>(visitor: &mut V, stmt: &stmt::Block) {
+    walk_stmts(visitor, &stmt.statements)
+}
+
+pub fn walk_if<V: Visitor>(visitor: &mut V, stmt: &stmt::If) {
+    visitor.visit_expr(&stmt.condition);
+    visitor.visit_stmt(&stmt.then_branch);
+    if let Some(else_branch) = &stmt.else_branch {
+        visitor.visit_stmt(else_branch);
+    }
+}
+
+pub fn walk_while<V: Visitor>(visitor: &mut V, stmt: &stmt::While) {
+    visitor.visit_expr(&stmt.condition);
+    visitor.visit_stmt(&stmt.body);
+}
+
+pub fn walk_function<V: Visitor>(visitor: &mut V, stmt: &stmt::Function) {
+    walk_stmts(visitor, &stmt.body)
+}
+
+pub fn walk_return<V: Visitor>(visitor: &mut V, stmt: &stmt::Return) {
+    if let Some(value) = &stmt.value {
+        visitor.visit_expr(value);
+    }
+}
+
+pub fn walk_class<V: Visitor>(visitor: &mut V, stmt: &stmt::Class) {
+    if let Some(superclass) = &stmt.superclass {
+        visitor.visit_variable(superclass);
+    }
+    for method in &stmt.methods {
+        visitor.visit_function(method);
+    }
+}
+
+pub fn walk_expr<V: Visitor>(visitor: &mut V, expr: &Expr) {
+    match expr {
+        Expr::Literal(v) => visitor.visit_literal(v),
+        Expr::Unary(v) => visitor.visit_unary(v),
+        Expr::Binary(v) => visitor.visit_binary(v),
+        Expr::Grouping(v) => visitor.visit_grouping(v),
+        Expr::Ternary(v) => visitor.visit_ternary(v),
+        Expr::Variable(v) => visitor.visit_variable(v),
+        Expr::Assign(v) => visitor.visit_assign(v),
+        Expr::Logical(v) => visitor.visit_logical(v),
+        Expr::Call(v) => visitor.visit_call(v),
+        Expr::Get(v) => visitor.visit_get(v),
+        Expr::Set(v) => visitor.visit_set(v),
+        Expr::This(v) => visitor.visit_this(v),
+        Expr::Super(v) => visitor.visit_super(v),
+        Expr::Interpolation(v) => visitor.visit_interpolation(v),
+    }
+}
+
+pub fn walk_unary<V: Visitor>(visitor: &mut V, expr: &Unary) {
+    visitor.visit_expr(&expr.right)
+}
+
+pub fn walk_binary<V: Visitor>(visitor: &mut V, expr: &Binary) {
+    visitor.visit_expr(&expr.left);
+    visitor.visit_expr(&expr.right);
+}
+
+pub fn walk_grouping<V: Visitor>(visitor: &mut V, expr: &Grouping) {
+    visitor.visit_expr(&expr.expr)
+}
+
+pub fn walk_ternary<V: Visitor>(visitor: &mut V, expr: &Ternary) {
+    visitor.visit_expr(&expr.left);
+    visitor.visit_expr(&expr.middle);
+    visitor.visit_expr(&expr.right);
+}
+
+pub fn walk_assign<V: Visitor>(visitor: &mut V, expr: &Assign) {
+    visitor.visit_expr(&expr.value)
+}
+
+pub fn walk_logical<V: Visitor>(visitor: &mut V, expr: &Logical) {
+    visitor.visit_expr(&expr.left);
+    visitor.visit_expr(&expr.right);
+}
+
+pub fn walk_call<V: Visitor>(visitor: &mut V, expr: &Call) {
+    visitor.visit_expr(&expr.callee);
+    for argument in &expr.arguments {
+        visitor.visit_expr(argument);
+    }
+}
+
+pub fn walk_get<V: Visitor>(visitor: &mut V, expr: &Get) {
+    visitor.visit_expr(&expr.object)
+}
+
+pub fn walk_set<V: Visitor>(visitor: &mut V, expr: &Set) {
+    visitor.visit_expr(&expr.object);
+    visitor.visit_expr(&expr.value);
+}
+
+pub fn walk_interpolation<V: Visitor>(visitor: &mut V, expr: &Interpolation) {
+    for part in &expr.parts {
+        visitor.visit_expr(part);
+    }
+}
+
+/// Like `Visitor`, but with mutable access so bindings and subtrees can be
+/// rewritten in place. Shared function declarations are cloned on write.
+pub trait VisitorMut: Sized {
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        walk_stmt_mut(self, stmt)
+    }
+
+    fn visit_expression_mut(&mut self, stmt: &mut stmt::Expression) {
+        walk_expression_mut(self, stmt)
+    }
+
+    fn visit_print_mut(&mut self, stmt: &mut stmt::Print) {
+        walk_print_mut(self, stmt)
+    }
+
+    fn visit_var_mut(&mut self, stmt: &mut stmt::Var) {
+        walk_var_mut(self, stmt)
+    }
+
+    fn visit_block_mut(&mut self, stmt: &mut stmt::Block) {
+        walk_block_mut(self, stmt)
+    }
+
+    fn visit_if_mut(&mut self, stmt: &mut stmt::If) {
+        walk_if_mut(self, stmt)
+    }
+
+    fn visit_while_mut(&mut self, stmt: &mut stmt::While) {
+        walk_while_mut(self, stmt)
+    }
+
+    fn visit_function_mut(&mut self, stmt: &mut stmt::Function) {
+        walk_function_mut(self, stmt)
+    }
+
+    fn visit_return_mut(&mut self, stmt: &mut stmt::Return) {
+        walk_return_mut(self, stmt)
+    }
+
+    fn visit_class_mut(&mut self, stmt: &mut stmt::Class) {
+        walk_class_mut(self, stmt)
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr)
+    }
+
+    fn visit_literal_mut(&mut self, _expr: &mut Literal) {}
+
+    fn visit_unary_mut(&mut self, expr: &mut Unary) {
+        walk_unary_mut(self, expr)
+    }
+
+    fn visit_binary_mut(&mut self, expr: &mut Binary) {
+        walk_binary_mut(self, expr)
+    }
+
+    fn visit_grouping_mut(&mut self, expr: &mut Grouping) {
+        walk_grouping_mut(self, expr)
+    }
+
+    fn visit_ternary_mut(&mut self, expr: &mut Ternary) {
+        walk_ternary_mut(self, expr)
+    }
+
+    fn visit_variable_mut(&mut self, _expr: &mut Variable) {}
+
+    fn visit_assign_mut(&mut self, expr: &mut Assign) {
+        walk_assign_mut(self, expr)
+    }
+
+    fn visit_logical_mut(&mut self, expr: &mut Logical) {
+        walk_logical_mut(self, expr)
+    }
+
+    fn visit_call_mut(&mut self, expr: &mut Call) {
+        walk_call_mut(self, expr)
+    }
+
+    fn visit_get_mut(&mut self, expr: &mut Get) {
+        walk_get_mut(self, expr)
+    }
+
+    fn visit_set_mut(&mut self, expr: &mut Set) {
+        walk_set_mut(self, expr)
+    }
+
+    fn visit_this_mut(&mut self, _expr: &mut This) {}
+
+    fn visit_super_mut(&mut self, _expr: &mut Super) {}
+
+    fn visit_interpolation_mut(&mut self, expr: &mut Interpolation) {
+        walk_interpolation_mut(self, expr)
+    }
+}
+
+pub fn walk_stmts_mut<V: VisitorMut>(visitor: &mut V, statements: &mut [Stmt]) {
+    for statement in statements {
+        visitor.visit_stmt_mut(statement);
+    }
+}
+
+pub fn walk_stmt_mut<V: VisitorMut>(visitor: &mut V, stmt: &mut Stmt) {
+    match stmt {
+        Stmt::Expression(v) => visitor.visit_expression_mut(v),
+        Stmt::Print(v) => visitor.visit_print_mut(v),
+        Stmt::Var(v) => visitor.visit_var_mut(v),
+        Stmt::Block(v) => visitor.visit_block_mut(v),
+        Stmt::If(v) => visitor.visit_if_mut(v),
+        Stmt::While(v) => visitor.visit_while_mut(v),
+        Stmt::Function(v) => visitor.visit_function_mut(Rc::make_mut(v)),
+        Stmt::Return(v) => visitor.visit_return_mut(v),
+        Stmt::Class(v) => visitor.visit_class_mut(v),
+    }
+}
+
+pub fn walk_expression_mut<V: VisitorMut>(visitor: &mut V, stmt: &mut stmt::Expression) {
+    visitor.visit_expr_mut(&mut stmt.expr)
+}
+
+pub fn walk_print_mut<V: VisitorMut>(visitor: &mut V, stmt: &mut stmt::Print) {
+    visitor.visit_expr_mut(&mut stmt.expr)
+}
+
+pub fn walk_var_mut<V: VisitorMut>(visitor: &mut V, stmt: &mut stmt::Var) {
+    if let Some(initializer) = &mut stmt.initializer {
+        visitor.visit_expr_mut(initializer);
+    }
+}
+
+pub fn walk_block_mut<V: VisitorMut>(visitor: &mut V, stmt: &mut stmt::Block) {
+    walk_stmts_mut(visitor, &mut stmt.statements)
+}
+
+pub fn walk_if_mut<V: VisitorMut>(visitor: &mut V, stmt: &mut stmt::If) {
+    visitor.visit_expr_mut(&mut stmt.condition);
+    visitor.visit_stmt_mut(&mut stmt.then_branch);
+    if let Some(else_branch) = &mut stmt.else_branch {
+        visitor.visit_stmt_mut(else_branch);
+    }
+}
+
+pub fn walk_while_mut<V: VisitorMut>(visitor: &mut V, stmt: &mut stmt::While) {
+    visitor.visit_expr_mut(&mut stmt.condition);
+    visitor.visit_stmt_mut(&mut stmt.body);
+}
+
+pub fn walk_function_mut<V: VisitorMut>(visitor: &mut V, stmt: &mut stmt::Function) {
+    walk_stmts_mut(visitor, &mut stmt.body)
+}
+
+pub fn walk_return_mut<V: VisitorMut>(visitor: &mut V, stmt: &mut stmt::Return) {
+    if let Some(value) = &mut stmt.value {
+        visitor.visit_expr_mut(value);
+    }
+}
+
+pub fn walk_class_mut<V: VisitorMut>(visitor: &mut V, stmt: &mut stmt::Class) {
+    if let Some(superclass) = &mut stmt.superclass {
+        visitor.visit_variable_mut(superclass);
+    }
+    for method in &mut stmt.methods {
+        visitor.visit_function_mut(Rc::make_mut(method));
+    }
+}
+
+pub fn walk_expr_mut<V: VisitorMut>(visitor: &mut V, expr: &mut Expr) {
+    match expr {
+        Expr::Literal(v) => visitor.visit_literal_mut(v),
+        Expr::Unary(v) => visitor.visit_unary_mut(v),
+        Expr::Binary(v) => visitor.visit_binary_mut(v),
+        Expr::Grouping(v) => visitor.visit_grouping_mut(v),
+        Expr::Ternary(v) => visitor.visit_ternary_mut(v),
+        Expr::Variable(v) => visitor.visit_variable_mut(v),
+        Expr::Assign(v) => visitor.visit_assign_mut(v),
+        Expr::Logical(v) => visitor.visit_logical_mut(v),
+        Expr::Call(v) => visitor.visit_call_mut(v),
+        Expr::Get(v) => visitor.visit_get_mut(v),
+        Expr::Set(v) => visitor.visit_set_mut(v),
+        Expr::This(v) => visitor.visit_this_mut(v),
+        Expr::Super(v) => visitor.visit_super_mut(v),
+        Expr::Interpolation(v) => visitor.visit_interpolation_mut(v),
+    }
+}
+
+pub fn walk_unary_mut<V: VisitorMut>(visitor: &mut V, expr: &mut Unary) {
+    visitor.visit_expr_mut(&mut expr.right)
+}
+
+pub fn walk_binary_mut<V: VisitorMut>(visitor: &mut V, expr: &mut Binary) {
+    visitor.visit_expr_mut(&mut expr.left);
+    visitor.visit_expr_mut(&mut expr.right);
+}
+
+pub fn walk_grouping_mut<V: VisitorMut>(visitor: &mut V, expr: &mut Grouping) {
+    visitor.visit_expr_mut(&mut expr.expr)
+}
+
+pub fn walk_ternary_mut<V: VisitorMut>(visitor: &mut V, expr: &mut Ternary) {
+    visitor.visit_expr_mut(&mut expr.left);
+    visitor.visit_expr_mut(&mut expr.middle);
+    visitor.visit_expr_mut(&mut expr.right);
+}
+
+pub fn walk_assign_mut<V: VisitorMut>(visitor: &mut V, expr: &mut Assign) {
+    visitor.visit_expr_mut(&mut expr.value)
+}
+
+pub fn walk_logical_mut<V: VisitorMut>(visitor: &mut V, expr: &mut Logical) {
+    visitor.visit_expr_mut(&mut expr.left);
+    visitor.visit_expr_mut(&mut expr.right);
+}
+
+pub fn walk_call_mut<V: VisitorMut>(visitor: &mut V, expr: &mut Call) {
+    visitor.visit_expr_mut(&mut expr.callee);
+    for argument in &mut expr.arguments {
+        visitor.visit_expr_mut(argument);
+    }
+}
+
+pub fn walk_get_mut<V: VisitorMut>(visitor: &mut V, expr: &mut Get) {
+    visitor.visit_expr_mut(&mut expr.object)
+}
+
+pub fn walk_set_mut<V: VisitorMut>(visitor: &mut V, expr: &mut Set) {
+    visitor.visit_expr_mut(&mut expr.object);
+    visitor.visit_expr_mut(&mut expr.value);
+}
+
+pub fn walk_interpolation_mut<V: VisitorMut>(visitor: &mut V, expr: &mut Interpolation) {
+    for part in &mut expr.parts {
+        visitor.visit_expr_mut(part);
+    }
+}
+
+/// Consumes a tree and builds a new one. Expression methods return an
+/// `Expr` rather than the node they were given, so a fold can replace a node
+/// with one of a different kind.
+pub trait Fold: Sized {
+    fn fold_stmt(&mut self, stmt: Stmt) -> Stmt {
+        fold_stmt(self, stmt)
+    }
+
+    fn fold_function(&mut self, stmt: stmt::Function) -> stmt::Function {
+        fold_function(self, stmt)
+    }
+
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        fold_expr(self, expr)
+    }
+
+    fn fold_literal(&mut self, expr: Literal) -> Expr {
+        Expr::Literal(expr)
+    }
+
+    fn fold_unary(&mut self, expr: Unary) -> Expr {
+        fold_unary(self, expr)
+    }
+
+    fn fold_binary(&mut self, expr: Binary) -> Expr {
+        fold_binary(self, expr)
+    }
+
+    fn fold_grouping(&mut self, expr: Grouping) -> Expr {
+        fold_grouping(self, expr)
+    }
+
+    fn fold_ternary(&mut self, expr: Ternary) -> Expr {
+        fold_ternary(self, expr)
+    }
+
+    fn fold_variable(&mut self, expr: Variable) -> Expr {
+        Expr::Variable(expr)
+    }
+
+    /// A superclass is always a variable, so it is not folded as an `Expr`.
+    fn fold_superclass(&mut self, expr: Variable) -> Variable {
+        expr
+    }
+
+    fn fold_assign(&mut self, expr: Assign) -> Expr {
+        fold_assign(self, expr)
+    }
+
+    fn fold_logical(&mut self, expr: Logical) -> Expr {
+        fold_logical(self, expr)
+    }
+
+    fn fold_call(&mut self, expr: Call) -> Expr {
+        fold_call(self, expr)
+    }
+
+    fn fold_get(&mut self, expr: Get) -> Expr {
+        fold_get(self, expr)
+    }
+
+    fn fold_set(&mut self, expr: Set) -> Expr {
+        fold_set(self, expr)
+    }
+
+    fn fold_this(&mut self, expr: This) -> Expr {
+        Expr::This(expr)
+    }
+
+    fn fold_super(&mut self, expr: Super) -> Expr {
+        Expr::Super(expr)
+    }
+
+    fn fold_interpolation(&mut self, expr: Interpolation) -> Expr {
+        fold_interpolation(self, expr)
+    }
+}
+
+pub fn fold_stmts<F: Fold>(folder: &mut F, statements: Vec<Stmt>) -> Vec<Stmt> {
+    statements
+        .into_iter()
+        .map(|statement| folder.fold_stmt(statement))
+        .collect()
+}
+
+/// Rebuilds a statement from its folded children. Statements are not
+/// replaced wholesale, so there is one function for all of them.
+pub fn fold_stmt<F: Fold>(folder: &mut F, stmt: Stmt) -> Stmt {
+    match stmt {
+        Stmt::Expression(v) => Stmt::Expression(stmt::Expression::new(folder.fold_expr(v.expr))),
+        Stmt::Print(v) => Stmt::Print(stmt::Print::new(folder.fold_expr(v.expr))),
+        Stmt::Var(v) => Stmt::Var(stmt::Var::new(
+            v.name,
+            v.initializer
+                .map(|initializer| folder.fold_expr(initializer)),
+        )),
+        Stmt::Block(v) => Stmt::Block(stmt::Block::new(fold_stmts(folder, v.statements))),
+        Stmt::If(v) => Stmt::If(stmt::If::new(
+            folder.fold_expr(v.condition),
+            folder.fold_stmt(*v.then_branch),
+            v.else_branch
+                .map(|else_branch| folder.fold_stmt(*else_branch)),
+        )),
+        Stmt::While(v) => Stmt::While(stmt::While::new(
+            folder.fold_expr(v.condition),
+            folder.fold_stmt(*v.body),
+        )),
+        Stmt::Function(v) => Stmt::Function(Rc::new(folder.fold_function(Rc::unwrap_or_clone(v)))),
+        Stmt::Return(v) => Stmt::Return(stmt::Return::new(
+            v.keyword,
+            v.value.map(|value| folder.fold_expr(value)),
+        )),
+        Stmt::Class(v) => Stmt::Class(stmt::Class::new(
+            v.name,
+            v.superclass
+                .map(|superclass| folder.fold_superclass(superclass)),
+            v.methods
+                .into_iter()
+                .map(|method| Rc::new(folder.fold_function(Rc::unwrap_or_clone(method))))
+                .collect(),
+        )),
+    }
+}
+
+pub fn fold_function<F: Fold>(folder: &mut F, stmt: stmt::Function) -> stmt::Function {
+    stmt::Function::new(stmt.name, stmt.params, fold_stmts(folder, stmt.body))
+}
+
+pub fn fold_expr<F: Fold>(folder: &mut F, expr: Expr) -> Expr {
+    match expr {
+        Expr::Literal(v) => folder.fold_literal(v),
+        Expr::Unary(v) => folder.fold_unary(v),
+        Expr::Binary(v) => folder.fold_binary(v),
+        Expr::Grouping(v) => folder.fold_grouping(v),
+        Expr::Ternary(v) => folder.fold_ternary(v),
+        Expr::Variable(v) => folder.fold_variable(v),
+        Expr::Assign(v) => folder.fold_assign(v),
+        Expr::Logical(v) => folder.fold_logical(v),
+        Expr::Call(v) => folder.fold_call(v),
+        Expr::Get(v) => folder.fold_get(v),
+        Expr::Set(v) => folder.fold_set(v),
+        Expr::This(v) => folder.fold_this(v),
+        Expr::Super(v) => folder.fold_super(v),
+        Expr::Interpolation(v) => folder.fold_interpolation(v),
+    }
+}
+
+pub fn fold_unary<F: Fold>(folder: &mut F, expr: Unary) -> Expr {
+    Expr::Unary(Unary {
+        right: Box::new(folder.fold_expr(*expr.right)),
+        ..expr
+    })
+}
+
+pub fn fold_binary<F: Fold>(folder: &mut F, expr: Binary) -> Expr {
+    Expr::Binary(Binary {
+        left: Box::new(folder.fold_expr(*expr.left)),
+        right: Box::new(folder.fold_expr(*expr.right)),
+        ..expr
+    })
+}
+
+pub fn fold_grouping<F: Fold>(folder: &mut F, expr: Grouping) -> Expr {
+    Expr::Grouping(Grouping {
+        expr: Box::new(folder.fold_expr(*expr.expr)),
+    })
+}
+
+pub fn fold_ternary<F: Fold>(folder: &mut F, expr: Ternary) -> Expr {
+    Expr::Ternary(Ternary {
+        left: Box::new(folder.fold_expr(*expr.left)),
+        middle: Box::new(folder.fold_expr(*expr.middle)),
+        right: Box::new(folder.fold_expr(*expr.right)),
+        ..expr
+    })
+}
+
+pub fn fold_assign<F: Fold>(folder: &mut F, expr: Assign) -> Expr {
+    Expr::Assign(Assign {
+        value: Box::new(folder.fold_expr(*expr.value)),
+        ..expr
+    })
+}
+
+pub fn fold_logical<F: Fold>(folder: &mut F, expr: Logical) -> Expr {
+    Expr::Logical(Logical {
+        left: Box::new(folder.fold_expr(*expr.left)),
+        right: Box::new(folder.fold_expr(*expr.right)),
+        ..expr
+    })
+}
+
+pub fn fold_call<F: Fold>(folder: &mut F, expr: Call) -> Expr {
+    Expr::Call(Call {
+        callee: Box::new(folder.fold_expr(*expr.callee)),
+        arguments: expr
+            .arguments
+            .into_iter()
+            .map(|argument| folder.fold_expr(argument))
+            .collect(),
+        ..expr
+    })
+}
+
+pub fn fold_get<F: Fold>(folder: &mut F, expr: Get) -> Expr {
+    Expr::Get(Get {
+        object: Box::new(folder.fold_expr(*expr.object)),
+        ..expr
+    })
+}
+
+pub fn fold_set<F: Fold>(folder: &mut F, expr: Set) -> Expr {
+    Expr::Set(Set {
+        object: Box::new(folder.fold_expr(*expr.object)),
+        value: Box::new(folder.fold_expr(*expr.value)),
+        ..expr
+    })
+}
+
+pub fn fold_interpolation<F: Fold>(folder: &mut F, expr: Interpolation) -> Expr {
+    Expr::Interpolation(Interpolation {
+        parts: expr
+            .parts
+            .into_iter()
+            .map(|part| folder.fold_expr(part))
+            .collect(),
+        ..expr
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{intern::Symbol, lexer::Lexer, parser::Parser};
+
+    use super::*;
+
+    fn parse(src: &str) -> Vec<Stmt> {
+        let (statements, errors) = Parser::new(Lexer::new(src)).parse_program();
+        assert!(errors.is_empty());
+        statements
+    }
+
+    #[test]
+    fn test_visitor_reaches_every_name() {
+        struct Names(Vec<String>);
+
+        impl Visitor for Names {
+            fn visit_var(&mut self, stmt: &stmt::Var) {
+                self.0.push(stmt.name.to_string());
+                walk_var(self, stmt);
+            }
+
+            fn visit_function(&mut self, stmt: &stmt::Function) {
+                self.0.push(stmt.name.to_string());
+                self.0
+                    .extend(stmt.params.iter().map(|param| param.to_string()));
+                walk_function(self, stmt);
+            }
+
+            fn visit_class(&mut self, stmt: &stmt::Class) {
+                self.0.push(stmt.name.to_string());
+                walk_class(self, stmt);
+            }
+
+            fn visit_variable(&mut self, expr: &Variable) {
+                self.0.push(expr.name.to_string());
+            }
+
+            fn visit_assign(&mut self, expr: &Assign) {
+                self.0.push(expr.name.to_string());
+                walk_assign(self, expr);
+            }
+
+            fn visit_get(&mut self, expr: &Get) {
+                walk_get(self, expr);
+                self.0.push(expr.name.to_string());
+            }
+        }
+
+        let statements = parse(
+            "class B < A { m(p, q) { return a ? b.c(d) : -e; } }
+             fun f() { while (g) { h = i or j; } }
+             if (k) print (l); else { var x = m; }",
+        );
+        let mut names = Names(Vec::new());
+        walk_stmts(&mut names, &statements);
+
+        assert_eq!(
+            names.0,
+            [
+                "B", "A", "m", "p", "q", "a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k",
+                "l", "x", "m"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fold_rebuilds_tree() {
+        struct Rewrite;
+
+        impl Fold for Rewrite {
+            fn fold_literal(&mut self, expr: Literal) -> Expr {
+                match expr.value {
+                    LiteralValue::Number(n) => Expr::Literal(Literal {
+                        value: LiteralValue::Number(n * 10.0),
+                        ..expr
+                    }),
+                    _ => Expr::Literal(expr),
+                }
+            }
+
+            fn fold_variable(&mut self, expr: Variable) -> Expr {
+                Expr::Literal(Literal {
+                    value: LiteralValue::String(Symbol::intern("x")),
+                    span: expr.name.span,
+                })
+            }
+        }
+
+        let statements = parse("fun f(a) { return a + 1; } print f(2) * (3);");
+        let statements = fold_stmts(&mut Rewrite, statements);
+
+        assert_eq!(
+            statements[0].to_string(),
+            "(fun f (a) { (return (+ x 10)); })"
+        );
+        assert_eq!(
+            statements[1].to_string(),
+            "(print (* (call x 20) (group 30)));"
+        );
+        let statements = fold_stmts(&mut Rewrite, parse("class B < A {}"));
+        assert!(matches!(&statements[0], Stmt::Class(class) if class.superclass.is_some()));
+
+        struct Variables(usize);
+
+        impl Fold for Variables {
+            fn fold_variable(&mut self, expr: Variable) -> Expr {
+                self.0 += 1;
+                Expr::Variable(expr)
+            }
+        }
+
+        let mut variables = Variables(0);
+        fold_stmts(&mut variables, parse("class B < A {} print a;"));
+        assert_eq!(variables.0, 1);
+    }
+}
@@ -0,0 +1,872 @@
+use std::{collections::HashMap, io::Write, rc::Rc};
+
+use crate::{
+    chunk::{Constant, FunctionProto, OpCode},
+    intern::Symbol,
+    interpreter::RuntimeError,
+    object::*,
+    token::{Span, Token},
+};
+
+/// Maximum call depth before the VM reports a stack overflow.
+pub const FRAMES_MAX: usize = 4096;
+
+#[derive(Debug)]
+struct CallFrame {
+    closure: ObjRef,
+    function: Rc<FunctionProto>,
+    ip: usize,
+    /// Stack index of slot 0.
+    base: usize,
+}
+
+/// Stack-based virtual machine running chunks produced by the `Compiler`.
+/// It gives the same output and errors as the tree-walking `Interpreter`.
+pub struct Vm {
+    heap: Heap,
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    globals: HashMap<Symbol, Value>,
+    /// Upvalues still pointing into the stack, ordered by slot.
+    open_upvalues: Vec<ObjRef>,
+    /// The heap string for each string constant loaded so far, so a literal
+    /// is allocated once rather than every time it is evaluated.
+    strings: HashMap<Symbol, ObjRef>,
+    out: Box<dyn Write>,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::with_output(Box::new(std::io::stdout()))
+    }
+
+    /// A VM that writes `print` output to `out` instead of stdout.
+    pub fn with_output(out: Box<dyn Write>) -> Self {
+        let mut vm = Vm {
+            heap: Heap::new(),
+            stack: Vec::new(),
+            frames: Vec::new(),
+            globals: HashMap::new(),
+            open_upvalues: Vec::new(),
+            strings: HashMap::new(),
+            out,
+        };
+
+        let clock = vm.alloc(Object::Native(Native {
+            arity: 0,
+            function: clock,
+        }));
+        vm.globals
+            .insert(Symbol::intern("clock"), Value::object(clock));
+        vm
+    }
+
+    /// Collect garbage before every allocation.
+    pub fn set_gc_stress(&mut self, stress: bool) {
+        self.heap.set_stress(stress);
+    }
+
+    pub fn gc_stats(&self) -> GcStats {
+        self.heap.stats()
+    }
+
+    /// Frees every object the program can no longer reach.
+    pub fn collect_garbage(&mut self) {
+        let roots = self
+            .stack
+            .iter()
+            .copied()
+            .chain(self.globals.values().copied())
+            .chain(self.frames.iter().map(|frame| Value::object(frame.closure)))
+            .chain(
+                self.open_upvalues
+                    .iter()
+                    .map(|&upvalue| Value::object(upvalue)),
+            )
+            .chain(self.strings.values().map(|&string| Value::object(string)));
+
+        self.heap.collect(roots);
+    }
+
+    /// Allocates `object`, collecting first if the heap has grown enough.
+    /// Anything the caller still needs must be reachable from a root.
+    fn alloc(&mut self, object: Object) -> ObjRef {
+        if self.heap.should_collect() {
+            self.collect_garbage();
+        }
+        self.heap.alloc(object)
+    }
+
+    pub fn interpret(&mut self, function: Rc<FunctionProto>) -> Result<(), RuntimeError> {
+        let closure = self.alloc(Object::Closure(Closure {
+            function,
+            upvalues: Vec::new(),
+        }));
+        self.stack.push(Value::object(closure));
+
+        let result = self
+            .call_closure(closure, 0, Span::new(0, 0))
+            .and_then(|()| self.run());
+
+        if result.is_err() {
+            self.stack.clear();
+            self.frames.clear();
+            self.open_upvalues.clear();
+        }
+        result
+    }
+
+    fn run(&mut self) -> Result<(), RuntimeError> {
+        loop {
+            let start = self.frame().ip;
+            let byte = self.read_u8();
+            let op = OpCode::try_from(byte)
+                .unwrap_or_else(|byte| unreachable!("invalid opcode {}", byte));
+
+            match op {
+                OpCode::Constant => {
+                    let value = match self.read_constant() {
+                        Constant::Number(v) => Value::number(v),
+                        Constant::String(v) => Value::object(self.string_constant(v)),
+                        Constant::Function(_) => unreachable!("functions load through Closure"),
+                    };
+                    self.push(value);
+                }
+                OpCode::Nil => self.push(Value::NIL),
+                OpCode::True => self.push(Value::boolean(true)),
+                OpCode::False => self.push(Value::boolean(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::GetLocal => {
+                    let slot = self.frame().base + self.read_u16() as usize;
+                    self.push(self.stack[slot]);
+                }
+                OpCode::SetLocal => {
+                    let slot = self.frame().base + self.read_u16() as usize;
+                    self.stack[slot] = self.peek(0);
+                }
+                OpCode::GetGlobal => {
+                    let name = self.read_name();
+                    match self.globals.get(&name) {
+                        Some(value) => self.push(*value),
+                        None => {
+                            return Err(RuntimeError::UndefinedVariable(
+                                name.to_string(),
+                                self.span(start),
+                            ))
+                        }
+                    }
+                }
+                OpCode::DefineGlobal => {
+                    let name = self.read_name();
+                    let value = self.pop();
+                    self.globals.insert(name, value);
+                }
+                OpCode::SetGlobal => {
+                    let name = self.read_name();
+                    let value = self.peek(0);
+                    match self.globals.get_mut(&name) {
+                        Some(slot) => *slot = value,
+                        None => {
+                            return Err(RuntimeError::UndefinedVariable(
+                                name.to_string(),
+                                self.span(start),
+                            ))
+                        }
+                    }
+                }
+                OpCode::GetUpvalue => {
+                    let index = self.read_u16() as usize;
+                    let upvalue = self.heap.closure(self.frame().closure).upvalues[index];
+                    let value = match self.heap.get(upvalue) {
+                        Object::Upvalue(Upvalue::Open(slot)) => self.stack[*slot],
+                        Object::Upvalue(Upvalue::Closed(value)) => *value,
+                        other => unreachable!("expected an upvalue, found {:?}", other),
+                    };
+                    self.push(value);
+                }
+                OpCode::SetUpvalue => {
+                    let index = self.read_u16() as usize;
+                    let upvalue = self.heap.closure(self.frame().closure).upvalues[index];
+                    let value = self.peek(0);
+                    match self.heap.get_mut(upvalue) {
+                        Object::Upvalue(Upvalue::Open(slot)) => {
+                            let slot = *slot;
+                            self.stack[slot] = value;
+                        }
+                        Object::Upvalue(Upvalue::Closed(closed)) => *closed = value,
+                        other => unreachable!("expected an upvalue, found {:?}", other),
+                    }
+                }
+                OpCode::GetProperty => {
+                    let name = self.read_name();
+                    let Some(instance) = self.as_instance(self.peek(0)) else {
+                        return Err(RuntimeError::NotAnInstance(self.span(start)));
+                    };
+
+                    let instance_ref = self.heap.instance(instance);
+                    let value = match instance_ref.fields.get(&name).copied() {
+                        Some(value) => value,
+                        None => {
+                            let class = instance_ref.class;
+                            self.bind_method(class, Value::object(instance), name, start)?
+                        }
+                    };
+
+                    self.pop();
+                    self.push(value);
+                }
+                OpCode::SetProperty => {
+                    let name = self.read_name();
+                    let value = self.pop();
+                    let object = self.pop();
+                    let Some(instance) = self.as_instance(object) else {
+                        return Err(RuntimeError::NotAnInstance(self.span(start)));
+                    };
+
+                    self.heap.instance_mut(instance).fields.insert(name, value);
+                    self.push(value);
+                }
+                OpCode::GetSuper => {
+                    let name = self.read_name();
+                    let Some(superclass) = self.peek(0).as_object() else {
+                        unreachable!("'super' is always a class");
+                    };
+                    let this = self.peek(1);
+                    let method = self.bind_method(superclass, this, name, start)?;
+                    self.pop_pair();
+                    self.push(method);
+                }
+                OpCode::Equal => {
+                    let (left, right) = self.pop_pair();
+                    let equal = self.heap.values_equal(left, right);
+                    self.push(Value::boolean(equal));
+                }
+                OpCode::NotEqual => {
+                    let (left, right) = self.pop_pair();
+                    let equal = self.heap.values_equal(left, right);
+                    self.push(Value::boolean(!equal));
+                }
+                OpCode::Greater => {
+                    let (l, r) = self.number_operands(op, start)?;
+                    self.push(Value::boolean(l > r));
+                }
+                OpCode::GreaterEqual => {
+                    let (l, r) = self.number_operands(op, start)?;
+                    self.push(Value::boolean(l >= r));
+                }
+                OpCode::Less => {
+                    let (l, r) = self.number_operands(op, start)?;
+                    self.push(Value::boolean(l < r));
+                }
+                OpCode::LessEqual => {
+                    let (l, r) = self.number_operands(op, start)?;
+                    self.push(Value::boolean(l <= r));
+                }
+                OpCode::Add => self.add(start)?,
+                OpCode::Subtract => {
+                    let (l, r) = self.number_operands(op, start)?;
+                    self.push(Value::number(l - r));
+                }
+                OpCode::Multiply => {
+                    let (l, r) = self.number_operands(op, start)?;
+                    self.push(Value::number(l * r));
+                }
+                OpCode::Divide => {
+                    let (l, r) = self.number_operands(op, start)?;
+                    if r == 0.0 {
+                        return Err(RuntimeError::DivisionByZero(self.operator(op, start)));
+                    }
+                    self.push(Value::number(l / r));
+                }
+                OpCode::Not => {
+                    let value = self.pop();
+                    self.push(Value::boolean(!value.is_truthy()));
+                }
+                OpCode::Negate => {
+                    let value = self.pop();
+                    match value.as_number() {
+                        Some(v) => self.push(Value::number(-v)),
+                        None => {
+                            return Err(RuntimeError::InvalidOperand(
+                                self.operator(op, start),
+                                self.heap.type_of(value),
+                            ))
+                        }
+                    }
+                }
+                OpCode::Ternary => {
+                    let right = self.pop();
+                    let middle = self.pop();
+                    let condition = self.pop();
+                    match condition.kind() {
+                        ValueKind::Boolean(true) => self.push(middle),
+                        ValueKind::Boolean(false) => self.push(right),
+                        _ => {
+                            return Err(RuntimeError::InvalidCondition(
+                                self.operator(op, start),
+                                self.heap.type_of(condition),
+                            ))
+                        }
+                    }
+                }
+                OpCode::Stringify => {
+                    let value = self.peek(0);
+                    let is_string = value
+                        .as_object()
+                        .is_some_and(|o| matches!(self.heap.get(o), Object::String(_)));
+                    if !is_string {
+                        let text = self.heap.display(value);
+                        let string = Value::object(self.alloc(Object::String(text.into())));
+                        self.pop();
+                        self.push(string);
+                    }
+                }
+                OpCode::Print => {
+                    let value = self.pop();
+                    let text = self.heap.display(value);
+                    writeln!(self.out, "{}", text).expect("failed to write output");
+                }
+                OpCode::Jump => {
+                    let offset = self.read_u16() as usize;
+                    self.frame_mut().ip += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_u16() as usize;
+                    if !self.peek(0).is_truthy() {
+                        self.frame_mut().ip += offset;
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = self.read_u16() as usize;
+                    self.frame_mut().ip -= offset;
+                }
+                OpCode::Call => {
+                    let count = self.read_u8() as usize;
+                    self.call_value(self.peek(count), count, self.span(start))?;
+                }
+                OpCode::Closure => {
+                    let Constant::Function(function) = self.read_constant() else {
+                        unreachable!("Closure operand is always a function");
+                    };
+
+                    let mut upvalues = Vec::with_capacity(function.upvalue_count);
+                    for _ in 0..function.upvalue_count {
+                        let is_local = self.read_u8() == 1;
+                        let index = self.read_u16() as usize;
+                        upvalues.push(if is_local {
+                            self.capture_upvalue(self.frame().base + index)
+                        } else {
+                            self.heap.closure(self.frame().closure).upvalues[index]
+                        });
+                    }
+
+                    let closure = self.alloc(Object::Closure(Closure { function, upvalues }));
+                    self.push(Value::object(closure));
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().expect("call frame");
+                    self.close_upvalues(frame.base);
+                    self.stack.truncate(frame.base);
+
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+                    self.push(result);
+                }
+                OpCode::Class => {
+                    let name = self.read_name();
+                    let class = self.alloc(Object::Class(Class {
+                        name,
+                        methods: HashMap::new(),
+                    }));
+                    self.push(Value::object(class));
+                }
+                OpCode::Inherit => {
+                    let superclass = match self.peek(1).as_object() {
+                        Some(v) if matches!(self.heap.get(v), Object::Class(_)) => v,
+                        _ => return Err(RuntimeError::SuperclassNotClass(self.span(start))),
+                    };
+                    let Some(subclass) = self.pop().as_object() else {
+                        unreachable!("Inherit always follows a class");
+                    };
+
+                    let methods = self.heap.class(superclass).methods.clone();
+                    self.heap.class_mut(subclass).methods.extend(methods);
+                }
+                OpCode::Method => {
+                    let name = self.read_name();
+                    let Some(method) = self.pop().as_object() else {
+                        unreachable!("Method always follows a closure");
+                    };
+                    let Some(class) = self.peek(0).as_object() else {
+                        unreachable!("Method always follows a class");
+                    };
+
+                    self.heap.class_mut(class).methods.insert(name, method);
+                }
+            }
+        }
+    }
+
+    fn call_value(&mut self, callee: Value, count: usize, span: Span) -> Result<(), RuntimeError> {
+        let Some(reference) = callee.as_object() else {
+            return Err(RuntimeError::NotCallable(span));
+        };
+
+        let base = self.stack.len() - count - 1;
+        match self.heap.get(reference) {
+            Object::Closure(_) => self.call_closure(reference, count, span),
+            Object::Native(native) => {
+                if count != native.arity {
+                    return Err(RuntimeError::ArityMismatch(native.arity, count, span));
+                }
+
+                let result = (native.function)(&self.stack[base + 1..]);
+                self.stack.truncate(base);
+                self.push(result);
+                Ok(())
+            }
+            Object::Class(class) => {
+                let initializer = class.methods.get(&Symbol::INIT).copied();
+                let instance = self.alloc(Object::Instance(Instance {
+                    class: reference,
+                    fields: HashMap::new(),
+                }));
+                self.stack[base] = Value::object(instance);
+
+                match initializer {
+                    Some(initializer) => self.call_closure(initializer, count, span),
+                    None if count != 0 => Err(RuntimeError::ArityMismatch(0, count, span)),
+                    None => Ok(()),
+                }
+            }
+            Object::BoundMethod(bound) => {
+                let method = bound.method;
+                self.stack[base] = bound.receiver;
+                self.call_closure(method, count, span)
+            }
+            _ => Err(RuntimeError::NotCallable(span)),
+        }
+    }
+
+    fn string_constant(&mut self, value: Symbol) -> ObjRef {
+        if let Some(&string) = self.strings.get(&value) {
+            return string;
+        }
+
+        let string = self.alloc(Object::String(value.as_str()));
+        self.strings.insert(value, string);
+        string
+    }
+
+    fn call_closure(
+        &mut self,
+        closure: ObjRef,
+        count: usize,
+        span: Span,
+    ) -> Result<(), RuntimeError> {
+        let function = Rc::clone(&self.heap.closure(closure).function);
+
+        if count != function.arity {
+            return Err(RuntimeError::ArityMismatch(function.arity, count, span));
+        }
+        if self.frames.len() == FRAMES_MAX {
+            return Err(RuntimeError::StackOverflow(span));
+        }
+
+        self.frames.push(CallFrame {
+            closure,
+            function,
+            ip: 0,
+            base: self.stack.len() - count - 1,
+        });
+        Ok(())
+    }
+
+    /// Looks `name` up on `class` and binds it to `receiver`.
+    fn bind_method(
+        &mut self,
+        class: ObjRef,
+        receiver: Value,
+        name: Symbol,
+        start: usize,
+    ) -> Result<Value, RuntimeError> {
+        match self.heap.class(class).methods.get(&name).copied() {
+            Some(method) => {
+                let bound = self.alloc(Object::BoundMethod(BoundMethod { receiver, method }));
+                Ok(Value::object(bound))
+            }
+            None => Err(RuntimeError::UndefinedProperty(
+                name.to_string(),
+                self.span(start),
+            )),
+        }
+    }
+
+    fn capture_upvalue(&mut self, slot: usize) -> ObjRef {
+        let position = self
+            .open_upvalues
+            .partition_point(|&upvalue| self.open_slot(upvalue) < slot);
+
+        if let Some(&existing) = self.open_upvalues.get(position) {
+            if self.open_slot(existing) == slot {
+                return existing;
+            }
+        }
+
+        let upvalue = self.alloc(Object::Upvalue(Upvalue::Open(slot)));
+        self.open_upvalues.insert(position, upvalue);
+        upvalue
+    }
+
+    /// Closes every open upvalue pointing at `from` or above.
+    fn close_upvalues(&mut self, from: usize) {
+        let position = self
+            .open_upvalues
+            .partition_point(|&upvalue| self.open_slot(upvalue) < from);
+
+        for upvalue in self.open_upvalues.split_off(position) {
+            let value = self.stack[self.open_slot(upvalue)];
+            *self.heap.get_mut(upvalue) = Object::Upvalue(Upvalue::Closed(value));
+        }
+    }
+
+    fn open_slot(&self, upvalue: ObjRef) -> usize {
+        match self.heap.get(upvalue) {
+            Object::Upvalue(Upvalue::Open(slot)) => *slot,
+            other => unreachable!("expected an open upvalue, found {:?}", other),
+        }
+    }
+
+    fn add(&mut self, start: usize) -> Result<(), RuntimeError> {
+        let (left, right) = self.pop_pair();
+
+        let value = match (left.kind(), right.kind()) {
+            (ValueKind::Number(l), ValueKind::Number(r)) => Value::number(l + r),
+            (ValueKind::Object(l), ValueKind::Object(r)) => {
+                match (self.heap.get(l), self.heap.get(r)) {
+                    (Object::String(l), Object::String(r)) => {
+                        let concatenated = format!("{}{}", l, r);
+                        Value::object(self.alloc(Object::String(concatenated.into())))
+                    }
+                    _ => return Err(self.invalid_operands(OpCode::Add, start, left, right)),
+                }
+            }
+            _ => return Err(self.invalid_operands(OpCode::Add, start, left, right)),
+        };
+
+        self.push(value);
+        Ok(())
+    }
+
+    fn number_operands(&mut self, op: OpCode, start: usize) -> Result<(f64, f64), RuntimeError> {
+        let (left, right) = self.pop_pair();
+        match (left.as_number(), right.as_number()) {
+            (Some(l), Some(r)) => Ok((l, r)),
+            _ => Err(self.invalid_operands(op, start, left, right)),
+        }
+    }
+
+    fn invalid_operands(
+        &self,
+        op: OpCode,
+        start: usize,
+        left: Value,
+        right: Value,
+    ) -> RuntimeError {
+        RuntimeError::InvalidOperands(
+            self.operator(op, start),
+            self.heap.type_of(left),
+            self.heap.type_of(right),
+        )
+    }
+
+    fn as_instance(&self, value: Value) -> Option<ObjRef> {
+        match value.as_object() {
+            Some(v) if matches!(self.heap.get(v), Object::Instance(_)) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Rebuilds the operator token of the instruction at `start`.
+    fn operator(&self, op: OpCode, start: usize) -> Token {
+        Token {
+            kind: op.operator(),
+            span: self.span(start),
+        }
+    }
+
+    fn span(&self, start: usize) -> Span {
+        self.frame().function.chunk.line_info(start).span
+    }
+
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().expect("call frame")
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().expect("call frame")
+    }
+
+    fn read_u8(&mut self) -> u8 {
+        let frame = self.frame_mut();
+        let byte = frame.function.chunk.code[frame.ip];
+        frame.ip += 1;
+        byte
+    }
+
+    fn read_u16(&mut self) -> u16 {
+        let frame = self.frame_mut();
+        let value = frame.function.chunk.read_u16(frame.ip);
+        frame.ip += 2;
+        value
+    }
+
+    fn read_constant(&mut self) -> Constant {
+        let index = self.read_u16() as usize;
+        self.frame().function.chunk.constants[index].clone()
+    }
+
+    fn read_name(&mut self) -> Symbol {
+        match self.read_constant() {
+            Constant::String(name) => name,
+            other => unreachable!("expected a name constant, found {:?}", other),
+        }
+    }
+
+    fn push(&mut self, value: Value) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("stack underflow")
+    }
+
+    fn pop_pair(&mut self) -> (Value, Value) {
+        let right = self.pop();
+        let left = self.pop();
+        (left, right)
+    }
+
+    fn peek(&self, distance: usize) -> Value {
+        self.stack[self.stack.len() - 1 - distance]
+    }
+}
+
+fn clock(_arguments: &[Value]) -> Value {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+
+    Value::number(now.as_secs_f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::{
+        compiler::Compiler, interpreter::Interpreter, lexer::Lexer, parser::Parser,
+        resolver::Resolver,
+    };
+
+    use super::*;
+
+    /// Collects everything printed by a backend.
+    #[derive(Clone, Default)]
+    struct Capture(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Capture {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+
+    /// Runs `src` on both backends and returns what each printed, followed
+    /// by the error and its span if there was one.
+    fn run_both(src: &str) -> (String, String) {
+        run_both_with(src, true)
+    }
+
+    fn run_both_with(src: &str, gc_stress: bool) -> (String, String) {
+        let (mut statements, errors) = Parser::new(Lexer::new(src)).parse_program();
+        assert!(errors.is_empty());
+        Resolver::new().resolve(&mut statements).unwrap();
+
+        let function = Compiler::new(src).compile(&statements).unwrap();
+        let vm_out = Capture::default();
+        let mut vm = Vm::with_output(Box::new(vm_out.clone()));
+        vm.set_gc_stress(gc_stress);
+        let vm_result = vm.interpret(Rc::new(function));
+
+        let tree_out = Capture::default();
+        let mut interpreter = Interpreter::with_output(Box::new(tree_out.clone()));
+        interpreter.set_gc_stress(gc_stress);
+        let tree_result = interpreter.execute(&statements);
+
+        let describe = |out: Capture, result: Result<(), RuntimeError>| match result {
+            Ok(()) => out.text(),
+            Err(e) => format!("{}error: {} at {}", out.text(), e, e.span()),
+        };
+
+        (describe(vm_out, vm_result), describe(tree_out, tree_result))
+    }
+
+    fn assert_same(src: &str, expected: &str) {
+        let (vm, tree) = run_both(src);
+        assert_eq!(vm, tree);
+        assert_eq!(vm, expected);
+    }
+
+    #[test]
+    fn test_arithmetic_and_strings() {
+        assert_same(
+            "print 1 + 2 * 3; print \"a\" + \"b\"; print 1 == 1; print \"a\" == \"a\"; print !nil; print (1, 2); print true ? 1 : 2;",
+            "7\nab\ntrue\ntrue\ntrue\n2\n1\n",
+        );
+    }
+
+    #[test]
+    fn test_interpolation() {
+        assert_same(
+            "fun f() {} class C {} var a = 1; print \"${a + 1} ${\"<${a}>\"} ${nil} ${f} ${C()}!\";",
+            "2 <1> nil <fn f> <C instance>!\n",
+        );
+    }
+
+    #[test]
+    fn test_control_flow_and_scopes() {
+        assert_same(
+            "var a = 1; { var a = 2; print a; } print a; for (var i = 0; i < 3; i = i + 1) { if (i == 1) print \"one\"; else print i; } print nil or \"x\"; print false and 1;",
+            "2\n1\n0\none\n2\nx\nfalse\n",
+        );
+    }
+
+    #[test]
+    fn test_closures() {
+        assert_same(
+            "fun counter() { var i = 0; fun inc() { i = i + 1; return i; } return inc; } var c = counter(); c(); print c(); print c; print clock;",
+            "2\n<fn inc>\n<native fn>\n",
+        );
+        assert_same(
+            "var fs = nil; { var a = 1; fun f() { return a; } a = 2; fs = f; } print fs();",
+            "2\n",
+        );
+    }
+
+    #[test]
+    fn test_classes() {
+        assert_same(
+            "class A { init(n) { this.n = n; } get() { return this.n; } } class B < A { get() { return super.get() + 1; } } var b = B(1); print b.get(); print b; print B; print b.init(5).n; var m = b.get; print m();",
+            "2\n<B instance>\nB\n5\n6\n",
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        for src in [
+            "print 1 - \"a\";",
+            "print -nil;",
+            "print 1 / 0;",
+            "print 1 ? 2 : 3;",
+            "print nope;",
+            "nope = 1;",
+            "\"x\"();",
+            "fun f(a) {} f();",
+            "class A {} A(1);",
+            "print 1.x;",
+            "class A {} print A().x;",
+            "var B = 1; class A < B {}",
+            "print \"a\"; print 1 + nil;",
+        ] {
+            let (vm, tree) = run_both(src);
+            assert!(vm.contains("error: "), "{}", src);
+            assert_eq!(vm, tree, "{}", src);
+        }
+    }
+
+    #[test]
+    fn test_collects_cycles() {
+        let src = "class Node { init() { fun get() { return this; } this.get = get; this.self = this; } } for (var i = 0; i < 100; i = i + 1) { Node(); } var kept = Node();";
+        let (mut statements, _) = Parser::new(Lexer::new(src)).parse_program();
+        Resolver::new().resolve(&mut statements).unwrap();
+        let function = Compiler::new(src).compile(&statements).unwrap();
+
+        let mut vm = Vm::new();
+        vm.interpret(Rc::new(function)).unwrap();
+        vm.collect_garbage();
+
+        let stats = vm.gc_stats();
+        assert_eq!(stats.collections, 1);
+        assert!(stats.objects_freed >= 100 * 3);
+        assert!(stats.bytes_live < stats.bytes_allocated / 10);
+
+        let kept = vm.globals[&Symbol::intern("kept")];
+        assert_eq!(vm.heap.display(kept), "<Node instance>");
+        let Some(kept) = kept.as_object() else {
+            panic!("expected an instance");
+        };
+        let get = vm.heap.instance(kept).fields[&Symbol::intern("get")];
+        assert_eq!(vm.heap.display(get), "<fn get>");
+    }
+
+    #[test]
+    fn test_string_constants_are_allocated_once() {
+        let src = "var s; for (var i = 0; i < 1000; i = i + 1) { s = \"literal\"; } print s;";
+        let (mut statements, _) = Parser::new(Lexer::new(src)).parse_program();
+        Resolver::new().resolve(&mut statements).unwrap();
+        let function = Compiler::new(src).compile(&statements).unwrap();
+
+        let mut vm = Vm::with_output(Box::new(Capture::default()));
+        vm.set_gc_stress(true);
+        vm.interpret(Rc::new(function)).unwrap();
+
+        assert_eq!(vm.strings.len(), 1);
+        assert!(vm.gc_stats().objects_freed < 10);
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        // Collecting at each of thousands of nested calls would take minutes.
+        let run_both = |src: &str| run_both_with(src, false);
+
+        let (vm, tree) = run_both("fun f() { f(); } f();");
+        assert_eq!(vm, tree);
+        assert!(vm.starts_with("error: Stack overflow"));
+
+        // The deepest call either backend allows, then one more.
+        let src = "fun f(n) { if (n > 0) f(n - 1); } f(DEPTH); print \"done\";";
+        let deepest = src.replace("DEPTH", &(FRAMES_MAX - 2).to_string());
+        assert_eq!(
+            run_both(&deepest),
+            ("done\n".to_owned(), "done\n".to_owned())
+        );
+        let too_deep = src.replace("DEPTH", &(FRAMES_MAX - 1).to_string());
+        let (vm, tree) = run_both(&too_deep);
+        assert_eq!(vm, tree);
+        assert!(vm.starts_with("error: Stack overflow"));
+    }
+}